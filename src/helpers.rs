@@ -0,0 +1,2383 @@
+//! Handlebars helpers for LaTeX templates
+//!
+//! Every helper here is a plain `fn` helper, so it can be passed to a recipe as
+//! `HandlebarsHelper::new("name", helpers::name)`, or all at once with `default_helpers`;
+//! `image_base64` and `signature` need a directory and return the `HandlebarsHelper` itself.
+
+use crate::{latex_escape, HandlebarsHelper};
+use handlebars::{
+    BlockContext, Context, Handlebars, Helper, HelperResult, JsonRender, JsonValue, Output,
+    RenderContext, RenderError, Renderable,
+};
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeSet;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+/// Returns the commonly needed helpers registered under their function names, besides `escape`,
+/// which every template has:
+///
+/// - `{{upper value}}` — `value` in uppercase
+/// - `{{lower value}}` — `value` in lowercase
+/// - `{{nl2par value}}` — `value` with every line break replaced by `\par`
+/// - `{{paragraphs value}}` — the blank-line separated paragraphs of `value`, escaped
+/// - `{{truncate value len=40 suffix="…"}}` — `value` cut to `len` characters
+/// - `{{join values sep=", " last=", and "}}` — the elements of `values` in one line, e.g.
+///   `A, B, and C`
+/// - `{{list values env="itemize"}}` — an `itemize` or `enumerate` list with an item per element
+/// - `{{table rows columns="l|c|r"}}` — a `tabular` with a row per element of `rows`
+/// - `{{href url text}}` — a `hyperref` link to `url` showing `text`, or the URL itself
+/// - `{{path value}}` — the file path `value` with `/` separators, escaped for the text
+/// - `{{verbatim value}}` — `value` printed literally in a typewriter font, e.g. `\verb|x_1|`
+/// - `{{currency value symbol="$" decimals=2}}` — `value` as a grouped, fixed-point amount
+/// - `{{percent value decimals=2}}` — the ratio `value` as a percentage, e.g. `12.34\%`
+/// - `{{math value}}` — the plain expression `value` as inline math, e.g. `$a \cdot b^{10}$`
+/// - `{{phone value pattern="(###) ###-####"}}` — the digits of `value` put into `pattern`
+/// - `{{plural count singular="item" plural="items"}}` — the word for `count`, e.g. `items`
+/// - `{{#present value}}...{{/present}}` — the block only if `value` is set and not blank
+/// - `{{#switch count}}{{#case 0}}...{{/case}}{{#case min=2}}...{{/case}}{{/switch}}` — the first
+///   `case` block matching the number `count`
+/// - `{{rule width="\\linewidth" thickness="0.4pt"}}` — a horizontal rule on a line of its own
+/// - `{{pagebreak}}` / `{{clearpage}}` — `\newpage` or `\clearpage`; as a block only after a body
+///   which is not blank
+/// - `{{roman value}}` / `{{alpha value}}` — `value` as a Roman numeral (`IV`) or letter (`d`)
+/// - `{{date value format="%d %B %Y"}}` — `value` parsed as a date and formatted (with the
+///   `chrono` feature)
+/// - `{{now format="%d %B %Y"}}` — the current local date and time, or UTC with `utc=true`,
+///   formatted (with the `chrono` feature)
+/// - `{{qrcode value size="3cm"}}` — a QR code encoding `value` (with the `qr` feature)
+/// - `{{markdown value}}` — `value` converted from Markdown into LaTeX (with the `markdown` feature)
+pub fn default_helpers() -> Vec<HandlebarsHelper> {
+    vec![
+        HandlebarsHelper::new("upper", upper),
+        HandlebarsHelper::new("lower", lower),
+        HandlebarsHelper::new("nl2par", nl2par),
+        HandlebarsHelper::new("paragraphs", paragraphs),
+        HandlebarsHelper::new("truncate", truncate),
+        HandlebarsHelper::new("join", join),
+        HandlebarsHelper::new("list", list),
+        HandlebarsHelper::new("table", table),
+        HandlebarsHelper::new("href", href),
+        HandlebarsHelper::new("path", path),
+        HandlebarsHelper::new("verbatim", verbatim),
+        HandlebarsHelper::new("currency", currency),
+        HandlebarsHelper::new("percent", percent),
+        HandlebarsHelper::new("math", math),
+        HandlebarsHelper::new("phone", phone),
+        HandlebarsHelper::new("plural", plural),
+        HandlebarsHelper::new("present", present),
+        HandlebarsHelper::new("switch", switch),
+        HandlebarsHelper::new("case", case),
+        HandlebarsHelper::new("rule", rule),
+        HandlebarsHelper::new("pagebreak", pagebreak),
+        HandlebarsHelper::new("clearpage", clearpage),
+        HandlebarsHelper::new("roman", roman),
+        HandlebarsHelper::new("alpha", alpha),
+        #[cfg(feature = "chrono")]
+        HandlebarsHelper::new("date", date),
+        #[cfg(feature = "chrono")]
+        HandlebarsHelper::new("now", now),
+        #[cfg(feature = "qr")]
+        HandlebarsHelper::new("qrcode", qrcode),
+        #[cfg(feature = "markdown")]
+        HandlebarsHelper::new("markdown", markdown),
+    ]
+}
+
+thread_local! {
+    /// Packages recorded by `require_package` on this thread since the last
+    /// `take_required_packages`
+    static REQUIRED_PACKAGES: RefCell<BTreeSet<String>> = const { RefCell::new(BTreeSet::new()) };
+}
+
+/// Records that the output of a helper needs the LaTeX `package`; `prepare_tex` loads the packages
+/// required while rendering a recipe after its `\documentclass`, unless the document already
+/// loads them. Custom helpers can call it as the built-in ones do, e.g. `href` for `hyperref`.
+pub fn require_package(package: &str) {
+    REQUIRED_PACKAGES.with(|packages| {
+        packages.borrow_mut().insert(package.to_string());
+    });
+}
+
+/// Returns and forgets the packages recorded with `require_package` on this thread
+pub(crate) fn take_required_packages() -> BTreeSet<String> {
+    REQUIRED_PACKAGES.with(|packages| packages.take())
+}
+
+/// Returns the rendered value of the parameter at `index` of helper `name`
+fn param_string(h: &Helper, name: &str, index: usize) -> Result<String, RenderError> {
+    h.param(index)
+        .map(|p| p.value().render())
+        .ok_or_else(|| RenderError::new(format!("Param {} is required for {} helper", index, name)))
+}
+
+/// `{{escape value}}` writes `value` with LaTeX special characters escaped (see `latex_escape`)
+pub fn escape(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let value = param_string(h, "escape", 0)?;
+    out.write(&latex_escape(&value))?;
+    Ok(())
+}
+
+/// `{{upper value}}` writes `value` in uppercase
+pub fn upper(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let value = param_string(h, "upper", 0)?;
+    out.write(&value.to_uppercase())?;
+    Ok(())
+}
+
+/// `{{lower value}}` writes `value` in lowercase
+pub fn lower(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let value = param_string(h, "lower", 0)?;
+    out.write(&value.to_lowercase())?;
+    Ok(())
+}
+
+/// `{{nl2par value}}` writes `value` with every line break replaced by `\par`; the text itself is
+/// not escaped
+pub fn nl2par(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let value = param_string(h, "nl2par", 0)?;
+    out.write(&value.lines().collect::<Vec<_>>().join("\\par\n"))?;
+    Ok(())
+}
+
+/// `{{paragraphs value}}` writes user-entered prose such as a notes field as LaTeX paragraphs:
+/// `value` is split at blank lines and the escaped paragraphs are joined by `\par`, while the
+/// single line breaks within a paragraph become spaces, as they read in the source
+pub fn paragraphs(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let value = param_string(h, "paragraphs", 0)?;
+
+    let mut paragraphs = Vec::new();
+    let mut lines = Vec::new();
+    for line in value.lines().map(str::trim).chain([""]) {
+        if !line.is_empty() {
+            lines.push(line);
+        } else if !lines.is_empty() {
+            paragraphs.push(latex_escape(&lines.join(" ")));
+            lines.clear();
+        }
+    }
+
+    out.write(&paragraphs.join("\n\\par\n"))?;
+    Ok(())
+}
+
+/// `{{truncate value len=40 suffix="…"}}` writes the first `len` characters of `value`, followed
+/// by `suffix` (`…` by default) if anything was cut off, with LaTeX special characters escaped
+///
+/// `len` counts characters rather than bytes, so a multibyte character is never split, and does
+/// not include the suffix; whitespace before the suffix is dropped.
+pub fn truncate(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let value = param_string(h, "truncate", 0)?;
+    let len = match h.hash_get("len").map(|v| v.value()) {
+        Some(v) => v.as_u64().ok_or_else(|| {
+            RenderError::new(format!(
+                "truncate helper expects len to be a non-negative integer, got {}",
+                v
+            ))
+        })? as usize,
+        None => return Err(RenderError::new("len is required for truncate helper")),
+    };
+    let suffix = h
+        .hash_get("suffix")
+        .map(|v| v.value().render())
+        .unwrap_or_else(|| "…".to_string());
+
+    let truncated = match value.char_indices().nth(len) {
+        Some((end, _)) => format!("{}{}", value[..end].trim_end(), suffix),
+        None => value,
+    };
+    out.write(&latex_escape(&truncated))?;
+    Ok(())
+}
+
+/// `{{join values sep=", " last=", and "}}` writes the LaTeX-escaped elements of the array
+/// `values` separated by `sep` (`, ` by default), with `last` (`sep` by default) before the last
+/// one, so `["A", "B", "C"]` becomes `A, B, and C`
+///
+/// Two elements are separated by `pair`, which defaults to `last` without a leading comma, giving
+/// `A and B`; a single element is written alone and an empty array writes nothing. The
+/// separators are written as they are, so they may contain LaTeX such as `\&`.
+pub fn join(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let param = h
+        .param(0)
+        .ok_or_else(|| RenderError::new("Param 0 is required for join helper"))?;
+    let values = param.value().as_array().ok_or_else(|| {
+        RenderError::new(format!(
+            "join helper expects an array, got {}",
+            param.value()
+        ))
+    })?;
+    let hash_string = |key: &str| h.hash_get(key).map(|v| v.value().render());
+    let sep = hash_string("sep").unwrap_or_else(|| ", ".to_string());
+    let last = hash_string("last");
+    let pair = hash_string("pair").unwrap_or_else(|| match &last {
+        Some(last) => last.strip_prefix(',').unwrap_or(last).to_string(),
+        None => sep.clone(),
+    });
+    let last = last.unwrap_or_else(|| sep.clone());
+
+    let items: Vec<String> = values.iter().map(|v| latex_escape(&v.render())).collect();
+    let joined = match items.as_slice() {
+        [] => String::new(),
+        [only] => only.clone(),
+        [first, second] => format!("{}{}{}", first, pair, second),
+        [init @ .., end] => format!("{}{}{}", init.join(&sep), last, end),
+    };
+    out.write(&joined)?;
+    Ok(())
+}
+
+/// `{{list values env="itemize"}}` writes the array `values` as a complete `itemize` (the default)
+/// or `enumerate` environment with one LaTeX-escaped `\item` per element
+///
+/// As a block, `{{#list values env="enumerate"}}{{name}} ({{price}}){{/list}}`, the block is
+/// rendered for every element, with the element as its context and `@index` set, and written
+/// as the item without escaping. An empty array writes nothing, as LaTeX rejects an empty list.
+pub fn list<'reg, 'rc>(
+    h: &Helper<'reg, 'rc>,
+    r: &'reg Handlebars<'reg>,
+    ctx: &'rc Context,
+    rc: &mut RenderContext<'reg, 'rc>,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let param = h
+        .param(0)
+        .ok_or_else(|| RenderError::new("Param 0 is required for list helper"))?;
+    let values = param.value().as_array().ok_or_else(|| {
+        RenderError::new(format!(
+            "list helper expects an array, got {}",
+            param.value()
+        ))
+    })?;
+    let env = h
+        .hash_get("env")
+        .map(|e| e.value().render())
+        .unwrap_or_else(|| "itemize".to_string());
+    if env != "itemize" && env != "enumerate" {
+        return Err(RenderError::new(format!(
+            "list helper expects env to be itemize or enumerate, got {}",
+            env
+        )));
+    }
+
+    if values.is_empty() {
+        return Ok(());
+    }
+
+    out.write(&format!("\\begin{{{}}}\n", env))?;
+    for (index, value) in values.iter().enumerate() {
+        out.write("\\item ")?;
+        match h.template() {
+            Some(t) => {
+                let mut block = BlockContext::new();
+                block.set_base_value(value.clone());
+                block.set_local_var("index", index.into());
+                rc.push_block(block);
+                let rendered = t.render(r, ctx, rc, out);
+                rc.pop_block();
+                rendered?;
+            }
+            None => out.write(&latex_escape(&value.render()))?,
+        }
+        out.write("\n")?;
+    }
+    out.write(&format!("\\end{{{}}}\n", env))?;
+    Ok(())
+}
+
+/// `{{table rows columns="l|c|r" keys="name,price"}}` writes the array `rows` as a complete
+/// `tabular` environment with `\hline` around every row and LaTeX-escaped cells
+///
+/// A row is an array of cells, or an object whose values under `keys` (all its keys by default)
+/// are the cells; for objects a header row with the keys comes first. `columns` is the column
+/// specification and defaults to `l` for each column. Short rows are padded with empty cells, and
+/// an empty `rows` writes a table without rows.
+pub fn table(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let param = h
+        .param(0)
+        .ok_or_else(|| RenderError::new("Param 0 is required for table helper"))?;
+    let rows = param.value().as_array().ok_or_else(|| {
+        RenderError::new(format!(
+            "table helper expects an array, got {}",
+            param.value()
+        ))
+    })?;
+
+    let keys: Option<Vec<String>> = match h.hash_get("keys") {
+        Some(keys) => Some(
+            keys.value()
+                .render()
+                .split(',')
+                .map(|key| key.trim().to_string())
+                .collect(),
+        ),
+        None => rows
+            .first()
+            .and_then(JsonValue::as_object)
+            .map(|row| row.keys().cloned().collect()),
+    };
+
+    let cell = |value: Option<&JsonValue>| match value {
+        None | Some(JsonValue::Null) => String::new(),
+        Some(value) => latex_escape(&value.render()),
+    };
+    let mut cells: Vec<Vec<String>> = Vec::new();
+    if let Some(keys) = &keys {
+        cells.push(keys.iter().map(|key| latex_escape(key)).collect());
+    }
+    for row in rows {
+        match (row, &keys) {
+            (JsonValue::Array(row), _) => cells.push(row.iter().map(|v| cell(Some(v))).collect()),
+            (JsonValue::Object(row), Some(keys)) => {
+                cells.push(keys.iter().map(|key| cell(row.get(key))).collect())
+            }
+            _ => {
+                return Err(RenderError::new(format!(
+                    "table helper expects every row to be an array or every row an object, got {}",
+                    row
+                )))
+            }
+        }
+    }
+
+    let width = cells.iter().map(Vec::len).max().unwrap_or(1).max(1);
+    let columns = h
+        .hash_get("columns")
+        .map(|c| c.value().render())
+        .unwrap_or_else(|| "l".repeat(width));
+
+    out.write(&format!("\\begin{{tabular}}{{{}}}\n\\hline\n", columns))?;
+    for mut row in cells {
+        row.resize(width, String::new());
+        out.write(&row.join(" & "))?;
+        out.write(" \\\\\n\\hline\n")?;
+    }
+    out.write("\\end{tabular}\n")?;
+    Ok(())
+}
+
+/// Escapes `url` for the URL argument of `\href` and `\url`: `#` and `%` are escaped with a
+/// backslash, while `\`, `{` and `}`, which cannot be escaped there, are percent-encoded
+fn escape_url(url: &str) -> String {
+    let mut escaped = String::with_capacity(url.len());
+    for c in url.chars() {
+        match c {
+            '#' | '%' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\\' => escaped.push_str("%5C"),
+            '{' => escaped.push_str("%7B"),
+            '}' => escaped.push_str("%7D"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// `{{href url text}}` writes a `\href` link to `url` with the LaTeX-escaped `text`, or a `\url`
+/// showing the URL itself when `text` is omitted; `url` may contain `%`, `#`, `_` and the like.
+/// Both need the `hyperref` package, which is required with `require_package`.
+pub fn href(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let url = escape_url(&param_string(h, "href", 0)?);
+    require_package("hyperref");
+    match h.param(1) {
+        Some(text) => out.write(&format!(
+            "\\href{{{}}}{{{}}}",
+            url,
+            latex_escape(&text.value().render())
+        ))?,
+        None => out.write(&format!("\\url{{{}}}", url))?,
+    }
+    Ok(())
+}
+
+/// `{{path value}}` writes the file path `value` with every `\` separator of a Windows path
+/// turned into `/` and repeated separators collapsed, LaTeX-escaped to be printed in the text,
+/// e.g. `C:\Data\my_report.tex` becomes `C:/Data/my\_report.tex`
+///
+/// Inside a command argument which takes a file name, as in
+/// `\includegraphics{ {{~path image argument=true~}} }`, the path has to stay unescaped: with
+/// `argument=true` only the separators are normalized, and a path containing `%`, `#`, `{` or `}`,
+/// which LaTeX cannot read there, is an error.
+pub fn path(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let value = param_string(h, "path", 0)?;
+    let normalized = normalize_path(&value);
+
+    let argument = h
+        .hash_get("argument")
+        .is_some_and(|v| v.value().as_bool() == Some(true));
+    if argument {
+        out.write(file_name_argument(&normalized, "path")?)?;
+    } else {
+        out.write(&latex_escape(&normalized))?;
+    }
+    Ok(())
+}
+
+/// Delimiters tried in order for `\verb`; letters, `*` and spaces cannot delimit it
+const VERB_DELIMITERS: &str = "|!+=/@;:'\"?.,-<>";
+
+/// `{{verbatim value}}` writes `value` literally in a typewriter font, for code, commands and
+/// other text with special characters: `\verb` with the first of `|`, `!`, `+`, ... which does
+/// not occur in `value`, or a `verbatim` environment when `value` spans several lines
+///
+/// `\verb` and `verbatim` cannot be used in the argument of another command, such as `\section`
+/// or `\footnote`; there `texttt=true` writes `\texttt` with every special character escaped and
+/// spaces kept, and its lines joined by `\newline`. The same is written when no delimiter is left
+/// for `\verb` or the lines contain `\end{verbatim}`.
+pub fn verbatim(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let value = param_string(h, "verbatim", 0)?;
+    let lines: Vec<&str> = value.lines().collect();
+    let texttt = h
+        .hash_get("texttt")
+        .is_some_and(|v| v.value().as_bool() == Some(true));
+
+    let delimiter = VERB_DELIMITERS.chars().find(|&c| !value.contains(c));
+    if lines.is_empty() {
+        return Ok(());
+    }
+    if !texttt && lines.len() == 1 {
+        if let Some(d) = delimiter {
+            out.write(&format!("\\verb{}{}{}", d, lines[0], d))?;
+            return Ok(());
+        }
+    }
+    if !texttt && lines.len() > 1 {
+        out.write(&verbatim_block(&lines))?;
+    } else {
+        out.write(&texttt_lines(&lines))?;
+    }
+    Ok(())
+}
+
+/// Returns `lines` as a `verbatim` environment, or as `texttt_lines` when they contain
+/// `\end{verbatim}`, which would end the environment early and leave the rest as live TeX
+fn verbatim_block(lines: &[&str]) -> String {
+    if lines.iter().any(|line| line.contains("\\end{verbatim}")) {
+        return texttt_lines(lines);
+    }
+    format!(
+        "\\begin{{verbatim}}\n{}\n\\end{{verbatim}}\n",
+        lines.join("\n")
+    )
+}
+
+/// Returns `lines` in one `\texttt` with every special character escaped, spaces kept and the
+/// lines joined by `\newline`
+fn texttt_lines(lines: &[&str]) -> String {
+    let escaped: Vec<String> = lines
+        .iter()
+        .map(|line| latex_escape(line).replace(' ', "\\ "))
+        .collect();
+    format!("\\texttt{{{}}}", escaped.join("\\newline\n"))
+}
+
+/// Returns `path` with `/` separators and repeated separators collapsed
+fn normalize_path(path: &str) -> String {
+    let mut normalized = String::with_capacity(path.len());
+    for c in path.chars() {
+        let c = if c == '\\' { '/' } else { c };
+        // a leading `//` (UNC share) is kept
+        if c == '/' && normalized.ends_with('/') && normalized.len() > 1 {
+            continue;
+        }
+        normalized.push(c);
+    }
+    normalized
+}
+
+/// Returns `path` for a file name argument, which cannot contain `%`, `#`, `{` or `}`; otherwise
+/// fails naming the helper `name`
+fn file_name_argument<'p>(path: &'p str, name: &str) -> Result<&'p str, RenderError> {
+    match path.chars().find(|c| matches!(c, '%' | '#' | '{' | '}')) {
+        Some(c) => Err(RenderError::new(format!(
+            "{} helper cannot write {} into a file name argument: {}",
+            name, c, path
+        ))),
+        None => Ok(path),
+    }
+}
+
+/// Decodes standard base64 `encoded`, ignoring whitespace; `None` when it is not valid base64
+fn decode_base64(encoded: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(encoded.len() / 4 * 3);
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+    let mut padding = 0;
+
+    for c in encoded.bytes().filter(|c| !c.is_ascii_whitespace()) {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => {
+                padding += 1;
+                continue;
+            }
+            _ => return None,
+        };
+        if padding > 0 {
+            return None;
+        }
+        bits = bits << 6 | u32::from(value);
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            decoded.push((bits >> bit_count) as u8);
+        }
+    }
+
+    // leftover bits of a truncated group must be padding zeros
+    if bit_count >= 6 || bits & ((1 << bit_count) - 1) != 0 || padding > 2 {
+        return None;
+    }
+    Some(decoded)
+}
+
+/// Decodes the base64 image `value` for the helper `helper` as `image_base64` does, writes it into
+/// `dir` unless it is there already and returns its file name
+fn write_base64_image(
+    h: &Helper,
+    dir: &Path,
+    value: &str,
+    helper: &str,
+) -> Result<String, RenderError> {
+    let ext = h
+        .hash_get("ext")
+        .map(|v| v.value().render())
+        .unwrap_or_else(|| "png".to_string());
+    if ext.is_empty() || !ext.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(RenderError::new(format!(
+            "{} helper expects ext to be a file extension, got {}",
+            helper, ext
+        )));
+    }
+
+    let encoded = match value.split_once(',') {
+        Some((prefix, data)) if prefix.starts_with("data:") => data,
+        _ => value,
+    };
+    let image = decode_base64(encoded)
+        .ok_or_else(|| RenderError::new(format!("{} helper expects base64 data", helper)))?;
+
+    let mut hasher = DefaultHasher::new();
+    image.hash(&mut hasher);
+    let name = format!("tex_tmpl_image_{:016x}.{}", hasher.finish(), ext);
+    let path = dir.join(&name);
+    if !path.exists() {
+        std::fs::write(&path, &image).map_err(|e| {
+            RenderError::new(format!("Cannot write image {}: {}", path.display(), e))
+        })?;
+    }
+    Ok(name)
+}
+
+/// Returns the `{{signature name date image="sig.png"}}` helper, which writes a signature block:
+/// a `minipage` with the signature image above a line and the escaped `name` and, if given,
+/// `date` below it, e.g. for the signing parties of a contract
+///
+/// The image is either a file path in `image`, found like any `\includegraphics` file, or base64
+/// data in `image_base64`, which is decoded into `dir` as with the `image_base64` helper (and
+/// `ext`); without either, blank space is left above the line to sign by hand. `width` sets the
+/// width of the block (`6cm` by default) and `height` the height of the image (`2cm`), both
+/// checked as by `dimension_param`.
+pub fn signature<P: Into<PathBuf>>(dir: P) -> HandlebarsHelper {
+    let dir = dir.into();
+
+    let helper = move |h: &Helper,
+                       _: &Handlebars,
+                       _: &Context,
+                       _: &mut RenderContext,
+                       out: &mut dyn Output|
+          -> HelperResult {
+        let name = param_string(h, "signature", 0)?;
+        let date = h.param(1).map(|p| p.value().render());
+        let width = dimension_param(h, "signature", "width", "6cm")?;
+        let height = dimension_param(h, "signature", "height", "2cm")?;
+
+        let image = match (h.hash_get("image"), h.hash_get("image_base64")) {
+            (Some(path), _) => Some(
+                file_name_argument(&normalize_path(&path.value().render()), "signature")?
+                    .to_string(),
+            ),
+            (None, Some(data)) => Some(write_base64_image(
+                h,
+                &dir,
+                &data.value().render(),
+                "signature",
+            )?),
+            (None, None) => None,
+        };
+
+        out.write(&format!(
+            "\\begin{{minipage}}[b]{{{}}}\n\\centering\n",
+            width
+        ))?;
+        match image {
+            Some(file) => {
+                require_package("graphicx");
+                out.write(&format!(
+                    "\\includegraphics[width=\\linewidth,height={},keepaspectratio]{{{}}}\\par\n",
+                    height, file
+                ))?;
+            }
+            None => out.write(&format!("\\vspace*{{{}}}\n", height))?,
+        }
+        out.write("\\rule{\\linewidth}{0.4pt}\\par\n")?;
+        out.write(&latex_escape(&name))?;
+        if let Some(date) = date.filter(|d| !d.trim().is_empty()) {
+            out.write("\\par\n")?;
+            out.write(&latex_escape(&date))?;
+        }
+        out.write("\n\\end{minipage}")?;
+        Ok(())
+    };
+
+    HandlebarsHelper::new("signature", helper)
+}
+
+/// Returns the `{{image_base64 value ext="png" width="3cm"}}` helper, which decodes the base64
+/// `value`, e.g. an uploaded image carried in the data, writes it into `dir` and emits an
+/// `\includegraphics` of it with the optional `width` and `height`; a `data:` URL prefix such as
+/// `data:image/png;base64,` is skipped
+///
+/// `dir` has to be where Tectonic looks for the image, i.e. the recipe's working directory (the
+/// template's directory by default) or one of `tectonic.asset_dirs`. The file is named after the
+/// hash of its contents, e.g. `tex_tmpl_image_5f3a....png`, so the same image is written once;
+/// the files are left in `dir`. `ext` (`png` by default) is the image type, e.g. `jpg` or `pdf`,
+/// `width` and `height` are checked as by `dimension_param`, and the `graphicx` package is
+/// required with `require_package`.
+pub fn image_base64<P: Into<PathBuf>>(dir: P) -> HandlebarsHelper {
+    let dir = dir.into();
+
+    let helper = move |h: &Helper,
+                       _: &Handlebars,
+                       _: &Context,
+                       _: &mut RenderContext,
+                       out: &mut dyn Output|
+          -> HelperResult {
+        let value = param_string(h, "image_base64", 0)?;
+        let name = write_base64_image(h, &dir, &value, "image_base64")?;
+
+        require_package("graphicx");
+        let mut options = Vec::new();
+        for key in ["width", "height"] {
+            if h.hash_get(key).is_some() {
+                options.push(format!(
+                    "{}={}",
+                    key,
+                    dimension_param(h, "image_base64", key, "")?
+                ));
+            }
+        }
+        if options.is_empty() {
+            out.write(&format!("\\includegraphics{{{}}}", name))?;
+        } else {
+            out.write(&format!(
+                "\\includegraphics[{}]{{{}}}",
+                options.join(","),
+                name
+            ))?;
+        }
+        Ok(())
+    };
+
+    HandlebarsHelper::new("image_base64", helper)
+}
+
+/// `{{currency value symbol="$" decimals=2 separator="," point="."}}` writes the numeric `value`
+/// rounded to `decimals` places with its integer part grouped by thousands, e.g. `1234567.5`
+/// becomes `\$1,234,567.50`; all arguments but `value` are optional, the symbol is prepended and
+/// everything is LaTeX-escaped
+pub fn currency(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let param = h
+        .param(0)
+        .ok_or_else(|| RenderError::new("Param 0 is required for currency helper"))?;
+    let number = match param.value() {
+        JsonValue::Number(n) => n.as_f64(),
+        JsonValue::String(s) => s.trim().parse::<f64>().ok(),
+        _ => None,
+    }
+    .filter(|n| n.is_finite())
+    .ok_or_else(|| {
+        RenderError::new(format!(
+            "currency helper expects a number, got {}",
+            param.value()
+        ))
+    })?;
+
+    let hash_string = |key: &str, default: &str| {
+        h.hash_get(key)
+            .map(|v| v.value().render())
+            .unwrap_or_else(|| default.to_string())
+    };
+    let symbol = hash_string("symbol", "");
+    let separator = hash_string("separator", ",");
+    let point = hash_string("point", ".");
+    let decimals = match h.hash_get("decimals").map(|v| v.value()) {
+        None => 2,
+        Some(v) => v.as_u64().ok_or_else(|| {
+            RenderError::new(format!(
+                "currency helper expects decimals to be a non-negative integer, got {}",
+                v
+            ))
+        })? as usize,
+    };
+
+    let fixed = format!("{:.*}", decimals, number.abs());
+    let (integer, fraction) = fixed.split_once('.').unwrap_or((&fixed, ""));
+
+    let mut grouped = String::new();
+    for (i, digit) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            grouped.push_str(&separator);
+        }
+        grouped.push(digit);
+    }
+    if !fraction.is_empty() {
+        grouped.push_str(&point);
+        grouped.push_str(fraction);
+    }
+
+    let sign = if number < 0.0 && fixed.chars().any(|c| c != '0' && c != '.') {
+        "-"
+    } else {
+        ""
+    };
+
+    out.write(&latex_escape(&format!("{}{}{}", sign, symbol, grouped)))?;
+    Ok(())
+}
+
+/// `{{plural count singular="item" plural="items" with_count=true}}` writes `singular` when the
+/// number `count` is 1 or -1 and `plural` otherwise, LaTeX-escaped; `plural` defaults to
+/// `singular` followed by `s`, and with `with_count=true` the count is written first, as in
+/// `3 items`
+pub fn plural(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let param = h
+        .param(0)
+        .ok_or_else(|| RenderError::new("Param 0 is required for plural helper"))?;
+    let count = match param.value() {
+        JsonValue::Number(n) => n.as_f64(),
+        JsonValue::String(s) => s.trim().parse::<f64>().ok(),
+        _ => None,
+    }
+    .ok_or_else(|| {
+        RenderError::new(format!(
+            "plural helper expects a number, got {}",
+            param.value()
+        ))
+    })?;
+    let singular = h
+        .hash_get("singular")
+        .map(|v| v.value().render())
+        .ok_or_else(|| RenderError::new("singular is required for plural helper"))?;
+
+    let word = if count.abs() == 1.0 {
+        singular
+    } else {
+        h.hash_get("plural")
+            .map(|v| v.value().render())
+            .unwrap_or_else(|| format!("{}s", singular))
+    };
+
+    let with_count = h
+        .hash_get("with_count")
+        .is_some_and(|v| v.value().as_bool() == Some(true));
+    if with_count {
+        out.write(&latex_escape(&format!(
+            "{} {}",
+            param.value().render(),
+            word
+        )))?;
+    } else {
+        out.write(&latex_escape(&word))?;
+    }
+    Ok(())
+}
+
+/// `{{phone value pattern="(###) ###-####"}}` writes the digits of `value`, ignoring any other
+/// characters, into the `#` placeholders of `pattern`, escaped, so `5551234567` and
+/// `555.123.4567` both become `(555) 123-4567`
+///
+/// When the number of digits does not match the placeholders, e.g. for an international number,
+/// `value` is written unchanged (but escaped) rather than cut or padded into the wrong shape.
+pub fn phone(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let value = param_string(h, "phone", 0)?;
+    let pattern = h
+        .hash_get("pattern")
+        .map(|p| p.value().render())
+        .unwrap_or_else(|| "(###) ###-####".to_string());
+
+    let digits: Vec<char> = value.chars().filter(char::is_ascii_digit).collect();
+    if digits.len() != pattern.matches('#').count() {
+        out.write(&latex_escape(value.trim()))?;
+        return Ok(());
+    }
+
+    let mut digits = digits.into_iter();
+    let formatted: String = pattern
+        .chars()
+        .map(|c| match c {
+            '#' => digits.next().unwrap_or(c),
+            _ => c,
+        })
+        .collect();
+    out.write(&latex_escape(&formatted))?;
+    Ok(())
+}
+
+/// `{{#present value}}...{{else}}...{{/present}}` renders the block only if `value` is present:
+/// not missing or null, not a string of only whitespace and not an empty array or object;
+/// otherwise the `{{else}}` block, if any, is rendered
+///
+/// Unlike `{{#if}}` the block keeps the surrounding context, and `"0"`, `0` or `false` count as
+/// present.
+pub fn present<'reg, 'rc>(
+    h: &Helper<'reg, 'rc>,
+    r: &'reg Handlebars<'reg>,
+    ctx: &'rc Context,
+    rc: &mut RenderContext<'reg, 'rc>,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let param = h
+        .param(0)
+        .ok_or_else(|| RenderError::new("Param 0 is required for present helper"))?;
+    let is_present = match param.value() {
+        JsonValue::Null => false,
+        JsonValue::String(s) => !s.trim().is_empty(),
+        JsonValue::Array(a) => !a.is_empty(),
+        JsonValue::Object(o) => !o.is_empty(),
+        _ => true,
+    };
+
+    match if is_present {
+        h.template()
+    } else {
+        h.inverse()
+    } {
+        Some(t) => t.render(r, ctx, rc, out),
+        None => Ok(()),
+    }
+}
+
+/// `{{rule width="\\linewidth" thickness="0.4pt"}}` writes a horizontal rule as a divider in a
+/// paragraph of its own, `\par\noindent\rule{\linewidth}{0.4pt}\par` with the defaults
+///
+/// `width` and `thickness` are TeX dimensions such as `0.5\textwidth` or `1mm`, checked as by
+/// `dimension_param`.
+pub fn rule(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    out.write(&format!(
+        "\\par\\noindent\\rule{{{}}}{{{}}}\\par",
+        dimension_param(h, "rule", "width", "\\linewidth")?,
+        dimension_param(h, "rule", "thickness", "0.4pt")?
+    ))?;
+    Ok(())
+}
+
+/// Returns the `key` hash parameter of helper `name`, or `default`, as a TeX dimension
+///
+/// Dimensions such as `0.5\textwidth` or `1mm` are written into commands as they are, so a value
+/// with other characters than letters, digits, spaces, `.`, `,`, `-`, `+`, `*`, `(`, `)` and `\`,
+/// which could break the command, is an error.
+fn dimension_param(
+    h: &Helper,
+    name: &str,
+    key: &str,
+    default: &str,
+) -> Result<String, RenderError> {
+    let value = h
+        .hash_get(key)
+        .map(|v| v.value().render())
+        .unwrap_or_else(|| default.to_string());
+    let valid = !value.trim().is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_alphanumeric() || " .,-+*()\\".contains(c));
+    if valid {
+        Ok(value)
+    } else {
+        Err(RenderError::new(format!(
+            "{} helper expects {} to be a TeX dimension, got {}",
+            name, key, value
+        )))
+    }
+}
+
+/// `{{pagebreak}}` writes `\newpage`; the block `{{#pagebreak}}...{{/pagebreak}}` writes its body
+/// followed by `\newpage`, or nothing when the body renders blank, so e.g. the sections of an
+/// `{{#each}}` are put on pages of their own while empty ones are skipped
+///
+/// Unlike a literal `\newpage` in the template the break is left out together with an empty
+/// section. A break after the last section does not add an empty page, and it is LaTeX's
+/// `\newpage` rather than `\pagebreak`, which would stretch the content to fill the page.
+pub fn pagebreak<'reg, 'rc>(
+    h: &Helper<'reg, 'rc>,
+    r: &'reg Handlebars<'reg>,
+    ctx: &'rc Context,
+    rc: &mut RenderContext<'reg, 'rc>,
+    out: &mut dyn Output,
+) -> HelperResult {
+    page_break(h, r, ctx, rc, out, "\\newpage")
+}
+
+/// `{{clearpage}}` and `{{#clearpage}}...{{/clearpage}}` work as `pagebreak` but write
+/// `\clearpage`, which also places the figures and tables still waiting for a page before the
+/// break
+pub fn clearpage<'reg, 'rc>(
+    h: &Helper<'reg, 'rc>,
+    r: &'reg Handlebars<'reg>,
+    ctx: &'rc Context,
+    rc: &mut RenderContext<'reg, 'rc>,
+    out: &mut dyn Output,
+) -> HelperResult {
+    page_break(h, r, ctx, rc, out, "\\clearpage")
+}
+
+/// Writes `command` on a line of its own, after the block of `h` if it has one, or nothing for a
+/// blank block
+fn page_break<'reg, 'rc>(
+    h: &Helper<'reg, 'rc>,
+    r: &'reg Handlebars<'reg>,
+    ctx: &'rc Context,
+    rc: &mut RenderContext<'reg, 'rc>,
+    out: &mut dyn Output,
+    command: &str,
+) -> HelperResult {
+    if let Some(t) = h.template() {
+        let body = t.renders(r, ctx, rc)?;
+        if body.trim().is_empty() {
+            return Ok(());
+        }
+        out.write(&body)?;
+    }
+    // the line break ends the command name before any letters following the helper
+    out.write(command)?;
+    out.write("\n")?;
+    Ok(())
+}
+
+/// Local variable through which `switch` passes its value to the `case` blocks inside it
+const SWITCH_VAR: &str = "tex_tmpl_switch";
+
+/// Returns the number `value` given to the helper `name`, either a JSON number or a numeric string
+fn param_number(h: &Helper, name: &str) -> Result<f64, RenderError> {
+    let param = h
+        .param(0)
+        .ok_or_else(|| RenderError::new(format!("Param 0 is required for {} helper", name)))?;
+    match param.value() {
+        JsonValue::Number(n) => n.as_f64(),
+        JsonValue::String(s) => s.trim().parse::<f64>().ok(),
+        _ => None,
+    }
+    .ok_or_else(|| {
+        RenderError::new(format!(
+            "{} helper expects a number, got {}",
+            name,
+            param.value()
+        ))
+    })
+}
+
+/// `{{#switch count}}...{{else}}...{{/switch}}` renders the first of the `{{#case}}` blocks inside
+/// it which matches the number `count`, or the `{{else}}` block when none does, e.g.
+///
+/// ```text
+/// {{#switch items.length}}
+///   {{#case 0}}No items.{{/case}}
+///   {{#case 1}}One item:{{/case}}
+///   {{#case min=2}}{{items.length}} items:{{/case}}
+/// {{/switch}}
+/// ```
+///
+/// Whitespace around the rendered block is dropped, so the cases can be put on lines of their own.
+pub fn switch<'reg, 'rc>(
+    h: &Helper<'reg, 'rc>,
+    r: &'reg Handlebars<'reg>,
+    ctx: &'rc Context,
+    rc: &mut RenderContext<'reg, 'rc>,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let value = param_number(h, "switch")?;
+    let Some(template) = h.template() else {
+        return Err(RenderError::new("switch helper has to be used as a block"));
+    };
+
+    // a switch nested in a case restores the state of the outer one afterwards
+    let outer = rc
+        .block()
+        .and_then(|block| block.get_local_var(SWITCH_VAR))
+        .cloned();
+    let set_state = |rc: &mut RenderContext<'reg, 'rc>, state: JsonValue| {
+        if let Some(block) = rc.block_mut() {
+            block.set_local_var(SWITCH_VAR, state);
+        }
+    };
+    set_state(rc, serde_json::json!({ "value": value, "matched": false }));
+
+    let body = template.renders(r, ctx, rc);
+    let matched = rc
+        .block()
+        .and_then(|block| block.get_local_var(SWITCH_VAR))
+        .is_some_and(|state| state["matched"] == true);
+    set_state(rc, outer.unwrap_or(JsonValue::Null));
+    let body = body?;
+
+    if matched {
+        out.write(body.trim())?;
+    } else if let Some(inverse) = h.inverse() {
+        out.write(inverse.renders(r, ctx, rc)?.trim())?;
+    }
+    Ok(())
+}
+
+/// `{{#case 1}}...{{/case}}` inside a `switch` renders its block if it is the first case matching
+/// the value of the switch: equal to the number given, or within the inclusive bounds
+/// `{{#case min=2 max=10}}`, of which either may be left out
+pub fn case<'reg, 'rc>(
+    h: &Helper<'reg, 'rc>,
+    r: &'reg Handlebars<'reg>,
+    ctx: &'rc Context,
+    rc: &mut RenderContext<'reg, 'rc>,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let state = rc
+        .block()
+        .and_then(|block| block.get_local_var(SWITCH_VAR))
+        .filter(|state| state.is_object())
+        .cloned()
+        .ok_or_else(|| RenderError::new("case helper has to be used inside a switch block"))?;
+    if state["matched"] == true {
+        return Ok(());
+    }
+    let value = state["value"].as_f64().unwrap_or(f64::NAN);
+
+    let bound = |key: &str| match h.hash_get(key).map(|v| v.value()) {
+        None => Ok(None),
+        Some(JsonValue::Number(n)) => Ok(n.as_f64()),
+        Some(v) => Err(RenderError::new(format!(
+            "case helper expects {} to be a number, got {}",
+            key, v
+        ))),
+    };
+    let (min, max) = (bound("min")?, bound("max")?);
+    let matches = if h.param(0).is_some() {
+        param_number(h, "case")? == value
+    } else if min.is_some() || max.is_some() {
+        min.is_none_or(|min| value >= min) && max.is_none_or(|max| value <= max)
+    } else {
+        return Err(RenderError::new(
+            "case helper expects a number or min and max bounds",
+        ));
+    };
+
+    if matches {
+        let mut state = state;
+        state["matched"] = true.into();
+        if let Some(block) = rc.block_mut() {
+            block.set_local_var(SWITCH_VAR, state);
+        }
+        if let Some(t) = h.template() {
+            t.render(r, ctx, rc, out)?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the one-based position given to the numbering helper `name`: its integer parameter,
+/// counted from the `from` argument (1 by default, 0 for `@index`)
+fn numbering_position(h: &Helper, name: &str) -> Result<u64, RenderError> {
+    let param = h
+        .param(0)
+        .ok_or_else(|| RenderError::new(format!("Param 0 is required for {} helper", name)))?;
+    let value = param.value().as_u64().ok_or_else(|| {
+        RenderError::new(format!(
+            "{} helper expects a non-negative integer, got {}",
+            name,
+            param.value()
+        ))
+    })?;
+    let from = match h.hash_get("from").map(|v| v.value()) {
+        None => 1,
+        Some(v) => v.as_u64().filter(|from| *from <= 1).ok_or_else(|| {
+            RenderError::new(format!(
+                "{} helper expects from to be 0 or 1, got {}",
+                name, v
+            ))
+        })?,
+    };
+
+    match value.checked_add(1 - from) {
+        Some(0) => Err(RenderError::new(format!(
+            "{} helper has no numeral for 0",
+            name
+        ))),
+        Some(position) => Ok(position),
+        None => Err(RenderError::new(format!(
+            "{} helper has no numeral for {} counted from 0",
+            name, value
+        ))),
+    }
+}
+
+/// Returns whether the numbering helper `name` writes uppercase, as `case="upper"` or
+/// `case="lower"` say, or `upper` without the argument
+fn numbering_uppercase(h: &Helper, name: &str, upper: bool) -> Result<bool, RenderError> {
+    match h.hash_get("case").map(|v| v.value().render()).as_deref() {
+        None => Ok(upper),
+        Some("upper") => Ok(true),
+        Some("lower") => Ok(false),
+        Some(case) => Err(RenderError::new(format!(
+            "{} helper expects case to be upper or lower, got {}",
+            name, case
+        ))),
+    }
+}
+
+/// `{{roman value from=1 case="upper"}}` writes the integer `value` as a Roman numeral, `I` for 1
+/// up to `MMMCMXCIX` for 3999; with `from=0` the count starts at 0 instead, so
+/// `{{roman @index from=0}}` numbers the iterations of an `{{#each}}` block `I`, `II`, `III`, and
+/// `case="lower"` writes `i`, `ii`, `iii`
+pub fn roman(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    const NUMERALS: [(u64, &str); 13] = [
+        (1000, "M"),
+        (900, "CM"),
+        (500, "D"),
+        (400, "CD"),
+        (100, "C"),
+        (90, "XC"),
+        (50, "L"),
+        (40, "XL"),
+        (10, "X"),
+        (9, "IX"),
+        (5, "V"),
+        (4, "IV"),
+        (1, "I"),
+    ];
+
+    let mut position = numbering_position(h, "roman")?;
+    if position > 3999 {
+        return Err(RenderError::new(format!(
+            "roman helper expects a number up to 3999, got {}",
+            position
+        )));
+    }
+
+    let mut numeral = String::new();
+    for (value, letters) in NUMERALS {
+        while position >= value {
+            numeral.push_str(letters);
+            position -= value;
+        }
+    }
+
+    if numbering_uppercase(h, "roman", true)? {
+        out.write(&numeral)?;
+    } else {
+        out.write(&numeral.to_lowercase())?;
+    }
+    Ok(())
+}
+
+/// `{{alpha value from=1 case="lower"}}` writes the integer `value` as a letter, `a` for 1 up to
+/// `z` for 26 and then `aa`, `ab` and so on; `from` and `case` work as for `roman`, so
+/// `({{alpha @index from=0}})` numbers the iterations of an `{{#each}}` block `(a)`, `(b)`, `(c)`
+pub fn alpha(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let mut position = numbering_position(h, "alpha")?;
+    let first = if numbering_uppercase(h, "alpha", false)? {
+        b'A'
+    } else {
+        b'a'
+    };
+
+    let mut letters = Vec::new();
+    while position > 0 {
+        position -= 1;
+        letters.push((first + (position % 26) as u8) as char);
+        position /= 26;
+    }
+
+    out.write(&letters.iter().rev().collect::<String>())?;
+    Ok(())
+}
+
+/// `{{math value}}` writes the expression `value`, e.g. `a/b + c^2` from semi-structured input, as
+/// inline math `$...$`. The transformation is kept minimal so that valid LaTeX math passes
+/// through: `*` becomes `\cdot` (except in a command such as `\operatorname*`), a multi-digit
+/// exponent or subscript is grouped (`x^10` becomes `x^{10}`) and `%`, `#`, `&` and `$` are
+/// escaped. A value already wrapped in `$...$` or `\(...\)` is not wrapped again, and a blank one
+/// writes nothing.
+pub fn math(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let value = h
+        .param(0)
+        .ok_or_else(|| RenderError::new("Param 0 is required for math helper"))?
+        .value()
+        .render();
+    let value = value.trim();
+    let expr = [("$", "$"), ("\\(", "\\)")]
+        .iter()
+        .find_map(|(open, close)| {
+            value
+                .strip_prefix(open)
+                .and_then(|v| v.strip_suffix(close))
+                .filter(|v| !v.contains('$'))
+        })
+        .unwrap_or(value)
+        .trim();
+
+    if !expr.is_empty() {
+        out.write(&format!("${}$", math_expression(expr)))?;
+    }
+    Ok(())
+}
+
+/// Applies the transformations of the `math` helper to the unwrapped `expr`
+fn math_expression(expr: &str) -> String {
+    let mut result = String::with_capacity(expr.len());
+    let mut chars = expr.chars().peekable();
+    // whether the last characters are the name of a `\command`
+    let mut command = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                result.push(c);
+                if let Some(next) = chars.next() {
+                    result.push(next);
+                    command = next.is_ascii_alphabetic();
+                }
+                continue;
+            }
+            '*' if !command => {
+                if !result.is_empty() && !result.ends_with(' ') {
+                    result.push(' ');
+                }
+                result.push_str("\\cdot");
+                if chars.peek().is_some_and(|next| !next.is_whitespace()) {
+                    result.push(' ');
+                }
+            }
+            '%' | '#' | '&' | '$' => {
+                result.push('\\');
+                result.push(c);
+            }
+            '^' | '_' => {
+                result.push(c);
+                let mut digits = String::new();
+                while let Some(digit) = chars.next_if(|d| d.is_ascii_digit()) {
+                    digits.push(digit);
+                }
+                if digits.len() > 1 {
+                    result.push_str(&format!("{{{}}}", digits));
+                } else {
+                    result.push_str(&digits);
+                }
+            }
+            _ => result.push(c),
+        }
+        command = command && c.is_ascii_alphabetic();
+    }
+    result
+}
+
+/// `{{percent value decimals=2 scaled=false}}` writes the ratio `value` multiplied by 100 and
+/// rounded to `decimals` places (2 by default) followed by an escaped `\%`, so `0.1234` becomes
+/// `12.34\%`; with `scaled=true` `value` already is a percentage and is not multiplied
+pub fn percent(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let param = h
+        .param(0)
+        .ok_or_else(|| RenderError::new("Param 0 is required for percent helper"))?;
+    let number = match param.value() {
+        JsonValue::Number(n) => n.as_f64(),
+        JsonValue::String(s) => s.trim().parse::<f64>().ok(),
+        _ => None,
+    }
+    .filter(|n| n.is_finite())
+    .ok_or_else(|| {
+        RenderError::new(format!(
+            "percent helper expects a number, got {}",
+            param.value()
+        ))
+    })?;
+    let decimals = match h.hash_get("decimals").map(|v| v.value()) {
+        None => 2,
+        Some(v) => v.as_u64().ok_or_else(|| {
+            RenderError::new(format!(
+                "percent helper expects decimals to be a non-negative integer, got {}",
+                v
+            ))
+        })? as usize,
+    };
+    let scaled = h
+        .hash_get("scaled")
+        .is_some_and(|v| v.value().as_bool() == Some(true));
+
+    let percentage = if scaled { number } else { number * 100.0 };
+    let formatted = format!("{:.*}", decimals, percentage);
+    // -0.001 rounds to -0.00, which should read 0.00
+    let formatted = match formatted.strip_prefix('-') {
+        Some(unsigned) if unsigned.chars().all(|c| c == '0' || c == '.') => unsigned.to_string(),
+        _ => formatted,
+    };
+
+    out.write(&format!("{}\\%", formatted))?;
+    Ok(())
+}
+
+/// `{{date value format="%d %B %Y"}}` parses `value` as an RFC 3339 / ISO 8601 date-time
+/// (`2024-03-14T10:00:00Z`), a date-time without an offset (`2024-03-14T10:00:00`) or a date
+/// (`2024-03-14`) and writes it formatted with the `chrono` `format`, which defaults to
+/// `%d %B %Y`
+#[cfg(feature = "chrono")]
+pub fn date(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    use chrono::{DateTime, NaiveDate, NaiveDateTime};
+
+    let value = param_string(h, "date", 0)?;
+    let format = date_format(h);
+
+    let parsed = DateTime::parse_from_rfc3339(&value)
+        .map(|dt| dt.naive_local())
+        .or_else(|_| NaiveDateTime::parse_from_str(&value, "%Y-%m-%dT%H:%M:%S%.f"))
+        .or_else(|_| NaiveDate::parse_from_str(&value, "%Y-%m-%d").map(|d| d.into()))
+        .map_err(|_| RenderError::new(format!("Cannot parse \"{}\" as a date", value)))?;
+
+    out.write(&format_date(parsed.format(&format), &format)?)?;
+    Ok(())
+}
+
+/// `{{now format="%d %B %Y" utc=false}}` writes the date and time of the rendering, in the local
+/// time zone or with `utc=true` in UTC, formatted like `date`, e.g. for "Generated on" lines
+/// without passing the date in the data
+#[cfg(feature = "chrono")]
+pub fn now(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    use chrono::{Local, Utc};
+
+    let format = date_format(h);
+    let utc = h
+        .hash_get("utc")
+        .is_some_and(|v| v.value().as_bool() == Some(true));
+
+    let formatted = if utc {
+        format_date(Utc::now().format(&format), &format)?
+    } else {
+        format_date(Local::now().format(&format), &format)?
+    };
+    out.write(&formatted)?;
+    Ok(())
+}
+
+/// Returns the `format` argument of a date helper, `%d %B %Y` by default
+#[cfg(feature = "chrono")]
+fn date_format(h: &Helper) -> String {
+    h.hash_get("format")
+        .map(|f| f.value().render())
+        .unwrap_or_else(|| "%d %B %Y".to_string())
+}
+
+/// Writes a date `formatted` with `format` into a string, failing for an invalid `format`
+#[cfg(feature = "chrono")]
+fn format_date(formatted: impl std::fmt::Display, format: &str) -> Result<String, RenderError> {
+    use std::fmt::Write;
+
+    let mut written = String::new();
+    write!(written, "{}", formatted)
+        .map_err(|_| RenderError::new(format!("Invalid date format \"{}\"", format)))?;
+    Ok(written)
+}
+
+/// `{{qrcode value size="3cm"}}` writes a QR code encoding `value` as a square box of `size`
+/// (a TeX dimension checked as by `dimension_param`, `3cm` by default), e.g. for an attendee ID on
+/// a ticket
+///
+/// The code is drawn with `\vrule`s, so it needs neither an image file nor a LaTeX package. It is
+/// drawn without the quiet zone; keep a light margin of a few modules around it, as scanners need.
+#[cfg(feature = "qr")]
+pub fn qrcode(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    use qrcode::{Color, QrCode};
+
+    let value = param_string(h, "qrcode", 0)?;
+    let size = dimension_param(h, "qrcode", "size", "3cm")?;
+
+    let code = QrCode::new(value.as_bytes()).map_err(|e| {
+        RenderError::new(format!("Cannot encode \"{}\" as a QR code: {}", value, e))
+    })?;
+    let width = code.width();
+    let colors = code.to_colors();
+
+    // \dimen0 is the module size; every row is a run-length encoded \hbox of rules and kerns
+    out.write(&format!(
+        "\\leavevmode\\begingroup\\offinterlineskip\\dimen0=\\dimexpr {}/{}\\relax\\vbox{{%\n",
+        size, width
+    ))?;
+    for row in colors.chunks(width) {
+        out.write("\\hbox{")?;
+        let mut modules = row.iter().peekable();
+        while let Some(&color) = modules.next() {
+            let mut run = 1;
+            while modules.next_if_eq(&&color).is_some() {
+                run += 1;
+            }
+            match color {
+                Color::Dark => {
+                    out.write(&format!("\\vrule width {}\\dimen0 height \\dimen0 ", run))?
+                }
+                Color::Light => out.write(&format!("\\kern {}\\dimen0 ", run))?,
+            }
+        }
+        out.write("}%\n")?;
+    }
+    out.write("}\\endgroup ")?;
+    Ok(())
+}
+
+/// `{{markdown value}}` writes the Markdown `value` converted into LaTeX (see
+/// `markdown_to_latex`)
+#[cfg(feature = "markdown")]
+pub fn markdown(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let value = param_string(h, "markdown", 0)?;
+    let latex = markdown_to_latex(&value);
+    if latex.contains("\\href{") {
+        require_package("hyperref");
+    }
+    out.write(&latex)?;
+    Ok(())
+}
+
+/// Converts CommonMark `markdown` into LaTeX
+///
+/// Headings become unnumbered `\section*` down to `\paragraph*`, emphasis `\emph` and `\textbf`,
+/// lists `itemize` and `enumerate`, block quotes `quote`, code `\texttt` and `verbatim` (or
+/// escaped `\texttt` lines, as with the `verbatim` helper, for code containing `\end{verbatim}`),
+/// and links `\href`, which needs the `hyperref` package. Images are replaced by their alt text and raw HTML
+/// is written as text. All text is LaTeX-escaped; as any input is valid Markdown this never fails.
+#[cfg(feature = "markdown")]
+pub fn markdown_to_latex(markdown: &str) -> String {
+    use pulldown_cmark::{Event, HeadingLevel, Parser, Tag, TagEnd};
+
+    fn heading(level: HeadingLevel) -> &'static str {
+        match level {
+            HeadingLevel::H1 => "section",
+            HeadingLevel::H2 => "subsection",
+            HeadingLevel::H3 => "subsubsection",
+            _ => "paragraph",
+        }
+    }
+
+    let mut latex = String::new();
+    // the text of the code block being read, written out at its end
+    let mut code_block: Option<String> = None;
+    for event in Parser::new(markdown) {
+        match event {
+            Event::Start(tag) => match tag {
+                Tag::Heading { level, .. } => {
+                    latex.push_str(&format!("\\{}*{{", heading(level)));
+                }
+                Tag::BlockQuote(_) => latex.push_str("\\begin{quote}\n"),
+                Tag::CodeBlock(_) => code_block = Some(String::new()),
+                Tag::List(None) => latex.push_str("\\begin{itemize}\n"),
+                Tag::List(Some(_)) => latex.push_str("\\begin{enumerate}\n"),
+                Tag::Item => latex.push_str("\\item "),
+                Tag::Emphasis => latex.push_str("\\emph{"),
+                Tag::Strong => latex.push_str("\\textbf{"),
+                Tag::Link { dest_url, .. } => {
+                    latex.push_str(&format!("\\href{{{}}}{{", escape_url(&dest_url)));
+                }
+                _ => {}
+            },
+            Event::End(tag) => match tag {
+                TagEnd::Paragraph => latex.push_str("\n\n"),
+                TagEnd::Heading(_) => latex.push_str("}\n\n"),
+                TagEnd::BlockQuote(_) => latex.push_str("\\end{quote}\n\n"),
+                TagEnd::CodeBlock => {
+                    let code = code_block.take().unwrap_or_default();
+                    let lines: Vec<&str> = code.lines().collect();
+                    latex.push_str(verbatim_block(&lines).trim_end());
+                    latex.push_str("\n\n");
+                }
+                TagEnd::List(false) => latex.push_str("\\end{itemize}\n\n"),
+                TagEnd::List(true) => latex.push_str("\\end{enumerate}\n\n"),
+                TagEnd::Item if !latex.ends_with('\n') => latex.push('\n'),
+                TagEnd::Emphasis | TagEnd::Strong | TagEnd::Link => latex.push('}'),
+                _ => {}
+            },
+            Event::Text(text) if code_block.is_some() => {
+                code_block.get_or_insert_with(String::new).push_str(&text)
+            }
+            Event::Text(text) | Event::Html(text) | Event::InlineHtml(text) => {
+                latex.push_str(&latex_escape(&text))
+            }
+            Event::Code(code) => latex.push_str(&format!("\\texttt{{{}}}", latex_escape(&code))),
+            Event::SoftBreak => latex.push('\n'),
+            Event::HardBreak => latex.push_str("\\\\\n"),
+            Event::Rule => latex.push_str("\\noindent\\rule{\\linewidth}{0.4pt}\n\n"),
+            _ => {}
+        }
+    }
+
+    latex.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prepare_tex_from_str;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_default_helpers() {
+        let mut data = HashMap::new();
+        data.insert("foo", "Boo & Zoo\nSecond line");
+
+        let output = prepare_tex_from_str(
+            "{{upper foo}}|{{lower foo}}|{{nl2par foo}}|{{escape foo}}",
+            &data,
+            Some(&default_helpers()),
+        );
+
+        assert_eq!(
+            output.unwrap(),
+            "BOO & ZOO\nSECOND LINE|boo & zoo\nsecond line|Boo & Zoo\\par\nSecond line|\
+             Boo \\& Zoo\nSecond line"
+        );
+    }
+
+    #[test]
+    fn test_truncate() {
+        let helpers = default_helpers();
+        let render = |value: &str, template: &str| {
+            let mut data = HashMap::new();
+            data.insert("v", value);
+            prepare_tex_from_str(template, &data, Some(&helpers))
+        };
+
+        assert_eq!(
+            render("Boo & Zoo deluxe", "{{truncate v len=9}}").unwrap(),
+            "Boo \\& Zoo…"
+        );
+        assert_eq!(
+            render("Čćžšđ ÄÖÜ", r#"{{truncate v len=3 suffix="..."}}"#).unwrap(),
+            "Čćž..."
+        );
+        assert_eq!(render("Boo", "{{truncate v len=3}}").unwrap(), "Boo");
+        assert_eq!(render("Boo", "{{truncate v len=0}}").unwrap(), "…");
+        assert!(render("Boo", "{{truncate v}}").is_err());
+        assert!(render("Boo", "{{truncate v len=-1}}").is_err());
+    }
+
+    #[test]
+    fn test_join() {
+        let helpers = default_helpers();
+        let render = |value: JsonValue, template: &str| {
+            let mut data = HashMap::new();
+            data.insert("v", value);
+            prepare_tex_from_str(template, &data, Some(&helpers))
+        };
+        let oxford = r#"{{join v sep=", " last=", and "}}"#;
+
+        assert_eq!(
+            render(serde_json::json!(["Alice", "Bob", "Carol & Co"]), oxford).unwrap(),
+            "Alice, Bob, and Carol \\& Co"
+        );
+        assert_eq!(
+            render(serde_json::json!(["Alice", "Bob"]), oxford).unwrap(),
+            "Alice and Bob"
+        );
+        assert_eq!(
+            render(serde_json::json!(["Alice"]), oxford).unwrap(),
+            "Alice"
+        );
+        assert_eq!(render(serde_json::json!([]), oxford).unwrap(), "");
+        assert_eq!(
+            render(serde_json::json!(["a", 1, "b"]), "{{join v}}").unwrap(),
+            "a, 1, b"
+        );
+        assert_eq!(
+            render(serde_json::json!(["a", "b"]), "{{join v}}").unwrap(),
+            "a, b"
+        );
+        assert_eq!(
+            render(
+                serde_json::json!(["a", "b"]),
+                r#"{{join v last=" and " pair=" \\& "}}"#
+            )
+            .unwrap(),
+            "a \\& b"
+        );
+        assert!(render(serde_json::json!("a"), "{{join v}}").is_err());
+    }
+
+    #[test]
+    fn test_list() {
+        let helpers = default_helpers();
+        let render = |value: JsonValue, template: &str| {
+            let mut data = HashMap::new();
+            data.insert("v", value);
+            prepare_tex_from_str(template, &data, Some(&helpers))
+        };
+
+        assert_eq!(
+            render(serde_json::json!(["Boo & Zoo", 42]), "{{list v}}").unwrap(),
+            "\\begin{itemize}\n\\item Boo \\& Zoo\n\\item 42\n\\end{itemize}\n"
+        );
+        assert_eq!(
+            render(
+                serde_json::json!([{"name": "boo"}, {"name": "zoo"}]),
+                r#"{{#list v env="enumerate"}}\textbf{ {{~name~}} } {{@index}}{{/list}}"#
+            )
+            .unwrap(),
+            "\\begin{enumerate}\n\\item \\textbf{boo} 0\n\\item \\textbf{zoo} 1\n\\end{enumerate}\n"
+        );
+        assert_eq!(render(serde_json::json!([]), "{{list v}}").unwrap(), "");
+        assert!(render(serde_json::json!("boo"), "{{list v}}")
+            .unwrap_err()
+            .to_string()
+            .contains("expects an array"));
+        assert!(render(serde_json::json!(["boo"]), r#"{{list v env="tabular"}}"#).is_err());
+    }
+
+    #[test]
+    fn test_table() {
+        let helpers = default_helpers();
+        let render = |value: JsonValue, template: &str| {
+            let mut data = HashMap::new();
+            data.insert("v", value);
+            prepare_tex_from_str(template, &data, Some(&helpers))
+        };
+
+        assert_eq!(
+            render(
+                serde_json::json!([["Boo & Zoo", 5, 1.5], ["zoo"]]),
+                r#"{{table v columns="l|c|r"}}"#
+            )
+            .unwrap(),
+            "\\begin{tabular}{l|c|r}\n\\hline\n\
+             Boo \\& Zoo & 5 & 1.5 \\\\\n\\hline\n\
+             zoo &  &  \\\\\n\\hline\n\
+             \\end{tabular}\n"
+        );
+        assert_eq!(
+            render(
+                serde_json::json!([{"name": "boo", "price": 5}, {"name": "zoo_1"}]),
+                r#"{{table v keys="name, price"}}"#
+            )
+            .unwrap(),
+            "\\begin{tabular}{ll}\n\\hline\n\
+             name & price \\\\\n\\hline\n\
+             boo & 5 \\\\\n\\hline\n\
+             zoo\\_1 &  \\\\\n\\hline\n\
+             \\end{tabular}\n"
+        );
+        assert_eq!(
+            render(serde_json::json!([]), "{{table v}}").unwrap(),
+            "\\begin{tabular}{l}\n\\hline\n\\end{tabular}\n"
+        );
+        assert!(render(serde_json::json!([["boo"], {"name": "zoo"}]), "{{table v}}").is_err());
+    }
+
+    #[test]
+    fn test_plural() {
+        let helpers = default_helpers();
+        let render = |count: JsonValue, template: &str| {
+            let mut data = HashMap::new();
+            data.insert("n", count);
+            prepare_tex_from_str(template, &data, Some(&helpers))
+        };
+        let items = r#"{{plural n singular="item" with_count=true}}"#;
+
+        assert_eq!(render(serde_json::json!(1), items).unwrap(), "1 item");
+        assert_eq!(render(serde_json::json!(3), items).unwrap(), "3 items");
+        assert_eq!(render(serde_json::json!(0), items).unwrap(), "0 items");
+        assert_eq!(render(serde_json::json!("1"), items).unwrap(), "1 item");
+        assert_eq!(
+            render(
+                serde_json::json!(2),
+                r#"{{plural n singular="child" plural="children"}}"#
+            )
+            .unwrap(),
+            "children"
+        );
+        assert_eq!(
+            render(serde_json::json!(1), r#"{{plural n singular="R&D unit"}}"#).unwrap(),
+            "R\\&D unit"
+        );
+        assert!(render(serde_json::json!("many"), items).is_err());
+        assert!(render(serde_json::json!(2), "{{plural n}}").is_err());
+    }
+
+    #[test]
+    fn test_present() {
+        let helpers = default_helpers();
+        let render = |data: JsonValue| {
+            prepare_tex_from_str(
+                r"{{#present tel}}Phone: {{tel}}{{else}}no phone{{/present}}",
+                &data,
+                Some(&helpers),
+            )
+            .unwrap()
+        };
+
+        assert_eq!(
+            render(serde_json::json!({"tel": "555 1234"})),
+            "Phone: 555 1234"
+        );
+        assert_eq!(render(serde_json::json!({"tel": 0})), "Phone: 0");
+        assert_eq!(render(serde_json::json!({})), "no phone");
+        assert_eq!(render(serde_json::json!({"tel": null})), "no phone");
+        assert_eq!(render(serde_json::json!({"tel": ""})), "no phone");
+        assert_eq!(render(serde_json::json!({"tel": " \t\n"})), "no phone");
+        assert_eq!(render(serde_json::json!({"tel": []})), "no phone");
+    }
+
+    #[test]
+    fn test_paragraphs() {
+        let helpers = default_helpers();
+        let render = |value: &str| {
+            let mut data = HashMap::new();
+            data.insert("notes", value);
+            prepare_tex_from_str("{{paragraphs notes}}", &data, Some(&helpers)).unwrap()
+        };
+
+        assert_eq!(
+            render("First line\r\ncontinued 100%\n\n\n  \nSecond & last\n"),
+            "First line continued 100\\%\n\\par\nSecond \\& last"
+        );
+        assert_eq!(render("one"), "one");
+        assert_eq!(render("\n \n"), "");
+    }
+
+    #[test]
+    fn test_phone() {
+        let helpers = default_helpers();
+        let render = |value: JsonValue, template: &str| {
+            let mut data = HashMap::new();
+            data.insert("v", value);
+            prepare_tex_from_str(template, &data, Some(&helpers)).unwrap()
+        };
+
+        assert_eq!(
+            render(serde_json::json!("5551234567"), "{{phone v}}"),
+            "(555) 123-4567"
+        );
+        assert_eq!(
+            render(serde_json::json!(5551234567u64), "{{phone v}}"),
+            "(555) 123-4567"
+        );
+        assert_eq!(
+            render(
+                serde_json::json!("01 23 45 67"),
+                r##"{{phone v pattern="+385 ## ### ###"}}"##
+            ),
+            "+385 01 234 567"
+        );
+        assert_eq!(
+            render(serde_json::json!("+44 20 7946 0958 #12"), "{{phone v}}"),
+            "+44 20 7946 0958 \\#12"
+        );
+
+        // a field named like a helper is written through an explicit path
+        let data = serde_json::json!({"phone": "555 1234"});
+        assert_eq!(
+            prepare_tex_from_str("{{this.phone}}", &data, Some(&helpers)).unwrap(),
+            "555 1234"
+        );
+    }
+
+    #[test]
+    fn test_switch() {
+        let helpers = default_helpers();
+        let render = |count: JsonValue| {
+            let data = serde_json::json!({ "count": count, "noun": "item" });
+            prepare_tex_from_str(
+                "{{#switch count}}
+                   {{#case 0}}no {{noun}}s{{/case}}
+                   {{#case 1}}one {{noun}}{{/case}}
+                   {{#case min=2 max=9}}a few {{noun}}s{{/case}}
+                   {{#case min=2}}many {{noun}}s{{/case}}
+                 {{else}}
+                   a fraction of an {{noun}}
+                 {{/switch}}",
+                &data,
+                Some(&helpers),
+            )
+        };
+
+        assert_eq!(render(0.into()).unwrap(), "no items");
+        assert_eq!(render("1".into()).unwrap(), "one item");
+        assert_eq!(render(2.into()).unwrap(), "a few items");
+        assert_eq!(render(9.into()).unwrap(), "a few items");
+        assert_eq!(render(10.into()).unwrap(), "many items");
+        assert_eq!(render(0.5.into()).unwrap(), "a fraction of an item");
+        assert!(render("none".into()).is_err());
+
+        let nested = prepare_tex_from_str(
+            "{{#switch a}}{{#case 1}}[{{#switch b}}{{#case 2}}b{{/case}}{{/switch}}]{{/case}}\
+             {{#case 1}}again{{/case}}{{/switch}}",
+            &serde_json::json!({"a": 1, "b": 2}),
+            Some(&helpers),
+        );
+        assert_eq!(nested.unwrap(), "[b]");
+        assert!(prepare_tex_from_str("{{#case 1}}x{{/case}}", &(), Some(&helpers)).is_err());
+    }
+
+    #[test]
+    fn test_verbatim() {
+        let helpers = default_helpers();
+        let render = |template: &str, value: &str| {
+            let data = serde_json::json!({ "code": value });
+            prepare_tex_from_str(template, &data, Some(&helpers)).unwrap()
+        };
+
+        assert_eq!(
+            render("{{verbatim code}}", r"\foo{x_1}"),
+            r"\verb|\foo{x_1}|"
+        );
+        assert_eq!(render("{{verbatim code}}", "a|b"), r"\verb!a|b!");
+        assert_eq!(render("{{verbatim code}}", "a|b!c"), r"\verb+a|b!c+");
+        assert_eq!(render("{{verbatim code}}", ""), "");
+        assert_eq!(
+            render("{{verbatim code}}", "fn main() {\n    x % 2\n}\n"),
+            "\\begin{verbatim}\nfn main() {\n    x % 2\n}\n\\end{verbatim}\n"
+        );
+        assert_eq!(
+            render("{{verbatim code texttt=true}}", r"a  $b\"),
+            r"\texttt{a\ \ \$b\textbackslash{}}"
+        );
+        assert_eq!(
+            render("{{verbatim code}}", r#"|!+=/@;:'"?.,-<>~"#),
+            r#"\texttt{|!+=/@;:'"?.,-<>\textasciitilde{}}"#
+        );
+        assert_eq!(
+            render("{{verbatim code}}", "\\end{verbatim}\n%"),
+            "\\texttt{\\textbackslash{}end\\{verbatim\\}\\newline\n\\%}"
+        );
+    }
+
+    #[test]
+    fn test_rule() {
+        let helpers = default_helpers();
+        let render = |template: &str| prepare_tex_from_str(template, &(), Some(&helpers));
+
+        assert_eq!(
+            render("{{rule}}").unwrap(),
+            "\\par\\noindent\\rule{\\linewidth}{0.4pt}\\par"
+        );
+        assert_eq!(
+            render(r#"{{rule width="0.5\\textwidth" thickness="1mm"}}"#).unwrap(),
+            "\\par\\noindent\\rule{0.5\\textwidth}{1mm}\\par"
+        );
+        assert!(render(r#"{{rule width="1cm}\\input{x"}}"#).is_err());
+        assert!(render(r#"{{rule thickness=""}}"#).is_err());
+    }
+
+    #[test]
+    fn test_pagebreak() {
+        let helpers = default_helpers();
+        let render = |template: &str| {
+            let data = serde_json::json!({"sections": ["Intro", "  ", "Results"]});
+            prepare_tex_from_str(template, &data, Some(&helpers)).unwrap()
+        };
+
+        assert_eq!(render("A{{pagebreak}}B"), "A\\newpage\nB");
+        assert_eq!(render("A{{clearpage}}B"), "A\\clearpage\nB");
+        assert_eq!(
+            render("{{#each sections}}{{#pagebreak}}{{this}}{{/pagebreak}}{{/each}}"),
+            "Intro\\newpage\nResults\\newpage\n"
+        );
+        assert_eq!(
+            render("{{#each sections}}{{#clearpage}}{{this}}{{/clearpage}}{{/each}}"),
+            "Intro\\clearpage\nResults\\clearpage\n"
+        );
+    }
+
+    #[test]
+    fn test_numbering() {
+        let helpers = default_helpers();
+        let render = |template: &str| {
+            let data = serde_json::json!({"items": ["boo", "zoo", "foo", "goo"], "n": 1994});
+            prepare_tex_from_str(template, &data, Some(&helpers))
+        };
+
+        assert_eq!(
+            render(r#"{{#each items}}{{roman @index from=0}}. {{/each}}"#).unwrap(),
+            "I. II. III. IV. "
+        );
+        assert_eq!(
+            render(r#"{{#each items}}({{alpha @index from=0}}){{/each}}"#).unwrap(),
+            "(a)(b)(c)(d)"
+        );
+        assert_eq!(
+            render(r#"{{roman n}} {{roman 9 case="lower"}} {{roman 3999}}"#).unwrap(),
+            "MCMXCIV ix MMMCMXCIX"
+        );
+        assert_eq!(
+            render(r#"{{alpha 26}} {{alpha 27}} {{alpha 702 case="upper"}} {{alpha 703}}"#)
+                .unwrap(),
+            "z aa ZZ aaa"
+        );
+        assert!(render("{{roman 0}}").is_err());
+        assert!(render("{{roman 4000}}").is_err());
+        assert!(render("{{roman 18446744073709551615}}").is_err());
+        assert!(render("{{alpha 18446744073709551615 from=0}}").is_err());
+        assert!(render("{{alpha 18446744073709551615}}").is_ok());
+        assert!(render("{{alpha -1}}").is_err());
+        assert!(render("{{alpha 1 from=2}}").is_err());
+        assert!(render(r#"{{roman 1 case="title"}}"#).is_err());
+    }
+
+    #[test]
+    fn test_href() {
+        let mut data = HashMap::new();
+        data.insert("url", "https://example.com/a_b?q=50%#top");
+        data.insert("text", "R&D");
+
+        assert_eq!(
+            prepare_tex_from_str(
+                "{{href url text}} {{href url}}",
+                &data,
+                Some(&default_helpers())
+            )
+            .unwrap(),
+            r"\href{https://example.com/a_b?q=50\%\#top}{R\&D} \url{https://example.com/a_b?q=50\%\#top}"
+        );
+    }
+
+    #[test]
+    fn test_path() {
+        let helpers = default_helpers();
+        let render = |value: &str, template: &str| {
+            let mut data = HashMap::new();
+            data.insert("v", value);
+            prepare_tex_from_str(template, &data, Some(&helpers))
+        };
+
+        assert_eq!(
+            render(r"C:\Users\Boo\\my_report #1.tex", "{{path v}}").unwrap(),
+            r"C:/Users/Boo/my\_report \#1.tex"
+        );
+        assert_eq!(
+            render(r"\\server\share\a~b.pdf", "{{path v}}").unwrap(),
+            r"//server/share/a\textasciitilde{}b.pdf"
+        );
+        assert_eq!(
+            render(
+                r"img\logo_v2.png",
+                r"\includegraphics{ {{~path v argument=true~}} }"
+            )
+            .unwrap(),
+            r"\includegraphics{img/logo_v2.png}"
+        );
+        assert!(render("img/50%.png", "{{path v argument=true}}").is_err());
+    }
+
+    #[test]
+    fn test_image_base64() {
+        let dir = tempfile::tempdir().expect("Temp dir cannot be created");
+        let helpers = [image_base64(dir.path())];
+        let render = |value: &str, template: &str| {
+            let mut data = HashMap::new();
+            data.insert("v", value);
+            prepare_tex_from_str(template, &data, Some(&helpers))
+        };
+
+        let tex = render("iVBORw0K\nGgo=", r#"{{image_base64 v width="3cm"}}"#).unwrap();
+        let name = tex
+            .strip_prefix("\\includegraphics[width=3cm]{")
+            .and_then(|rest| rest.strip_suffix('}'))
+            .unwrap();
+        assert!(name.ends_with(".png"));
+        assert_eq!(
+            std::fs::read(dir.path().join(name)).unwrap(),
+            b"\x89PNG\r\n\x1a\n"
+        );
+
+        assert_eq!(
+            render("data:image/png;base64,iVBORw0KGgo=", "{{image_base64 v}}").unwrap(),
+            format!("\\includegraphics{{{}}}", name)
+        );
+        assert!(render("Ym9v", r#"{{image_base64 v ext="jpg"}}"#)
+            .unwrap()
+            .ends_with(".jpg}"));
+        assert!(render("not base64!", "{{image_base64 v}}").is_err());
+        assert!(render("Ym9v", r#"{{image_base64 v ext="../x"}}"#).is_err());
+        assert!(render("Ym9v", r#"{{image_base64 v width="3cm]{x}\input{y}%"}}"#).is_err());
+        assert!(render("Ym9v", r#"{{image_base64 v height=""}}"#).is_err());
+    }
+
+    #[test]
+    fn test_signature() {
+        let dir = tempfile::tempdir().expect("Temp dir cannot be created");
+        let helpers = [signature(dir.path())];
+        let render = |template: &str| {
+            let data = serde_json::json!({
+                "name": "Ana Horvat & Co",
+                "date": "14 March 2024",
+                "scan": "data:image/png;base64,iVBORw0KGgo=",
+            });
+            prepare_tex_from_str(template, &data, Some(&helpers))
+        };
+
+        assert_eq!(
+            render(r#"{{signature name date image="C:\\sig\\ana.png" width="5cm"}}"#).unwrap(),
+            "\\begin{minipage}[b]{5cm}\n\\centering\n\
+             \\includegraphics[width=\\linewidth,height=2cm,keepaspectratio]{C:/sig/ana.png}\\par\n\
+             \\rule{\\linewidth}{0.4pt}\\par\n\
+             Ana Horvat \\& Co\\par\n14 March 2024\n\
+             \\end{minipage}"
+        );
+        assert_eq!(
+            render("{{signature name}}").unwrap(),
+            "\\begin{minipage}[b]{6cm}\n\\centering\n\\vspace*{2cm}\n\
+             \\rule{\\linewidth}{0.4pt}\\par\nAna Horvat \\& Co\n\\end{minipage}"
+        );
+
+        let tex = render("{{signature name date image_base64=scan}}").unwrap();
+        let name = tex
+            .split("keepaspectratio]{")
+            .nth(1)
+            .and_then(|rest| rest.split('}').next())
+            .unwrap();
+        assert_eq!(
+            std::fs::read(dir.path().join(name)).unwrap(),
+            b"\x89PNG\r\n\x1a\n"
+        );
+        assert!(render(r#"{{signature name image="50%.png"}}"#).is_err());
+        assert!(render(r#"{{signature name width="5cm}\input{x"}}"#).is_err());
+        assert!(render(r#"{{signature name height=""}}"#).is_err());
+    }
+
+    #[test]
+    fn test_currency() {
+        let helpers = default_helpers();
+        let render = |value: JsonValue, template: &str| {
+            let mut data = HashMap::new();
+            data.insert("v", value);
+            prepare_tex_from_str(template, &data, Some(&helpers))
+        };
+
+        assert_eq!(
+            render(1234567.5.into(), r#"{{currency v symbol="$"}}"#).unwrap(),
+            r"\$1,234,567.50"
+        );
+        assert_eq!(
+            render(
+                (-1234).into(),
+                r#"{{currency v symbol="€ " separator="." point="," decimals=1}}"#
+            )
+            .unwrap(),
+            "-€ 1.234,0"
+        );
+        assert_eq!(
+            render(999.999.into(), "{{currency v}}").unwrap(),
+            "1,000.00"
+        );
+        assert_eq!(
+            render("42".into(), "{{currency v decimals=0}}").unwrap(),
+            "42"
+        );
+        assert!(render("n/a".into(), "{{currency v}}")
+            .unwrap_err()
+            .to_string()
+            .contains("expects a number"));
+    }
+
+    #[test]
+    fn test_percent() {
+        let helpers = default_helpers();
+        let render = |value: JsonValue, template: &str| {
+            let mut data = HashMap::new();
+            data.insert("v", value);
+            prepare_tex_from_str(template, &data, Some(&helpers))
+        };
+
+        assert_eq!(
+            render(serde_json::json!(0.1234), "{{percent v}}").unwrap(),
+            "12.34\\%"
+        );
+        assert_eq!(
+            render(serde_json::json!(1), "{{percent v decimals=0}}").unwrap(),
+            "100\\%"
+        );
+        assert_eq!(
+            render(
+                serde_json::json!("42.5"),
+                "{{percent v scaled=true decimals=1}}"
+            )
+            .unwrap(),
+            "42.5\\%"
+        );
+        assert_eq!(
+            render(serde_json::json!(-0.00001), "{{percent v}}").unwrap(),
+            "0.00\\%"
+        );
+        assert_eq!(
+            render(serde_json::json!(-0.5), "{{percent v}}").unwrap(),
+            "-50.00\\%"
+        );
+        assert!(render(serde_json::json!("half"), "{{percent v}}").is_err());
+    }
+
+    #[test]
+    fn test_math() {
+        let helpers = default_helpers();
+        let render = |value: &str| {
+            let mut data = HashMap::new();
+            data.insert("v", value);
+            prepare_tex_from_str("{{math v}}", &data, Some(&helpers))
+        };
+
+        assert_eq!(render("a/b + c^2").unwrap(), "$a/b + c^2$");
+        assert_eq!(
+            render("2*x^10 * y_12").unwrap(),
+            "$2 \\cdot x^{10} \\cdot y_{12}$"
+        );
+        assert_eq!(render("50% of n").unwrap(), "$50\\% of n$");
+        assert_eq!(
+            render("$\\frac{a}{b} \\cdot x_{ij}$").unwrap(),
+            "$\\frac{a}{b} \\cdot x_{ij}$"
+        );
+        assert_eq!(
+            render("\\(\\operatorname*{arg\\,max}_x f\\)").unwrap(),
+            "$\\operatorname*{arg\\,max}_x f$"
+        );
+        assert_eq!(render("$a$ and $b$").unwrap(), "$\\$a\\$ and \\$b\\$$");
+        assert_eq!(render("  ").unwrap(), "");
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_date() {
+        let helpers = default_helpers();
+        let render = |value: &str, template: &str| {
+            let mut data = HashMap::new();
+            data.insert("d", value.to_string());
+            prepare_tex_from_str(template, &data, Some(&helpers))
+        };
+
+        assert_eq!(
+            render("2024-03-14T10:00:00+01:00", "{{date d}}").unwrap(),
+            "14 March 2024"
+        );
+        assert_eq!(
+            render("2024-03-14", r#"{{date d format="%Y/%m/%d"}}"#).unwrap(),
+            "2024/03/14"
+        );
+        assert!(render("yesterday", "{{date d}}")
+            .unwrap_err()
+            .to_string()
+            .contains("yesterday"));
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_now() {
+        let helpers = default_helpers();
+        let render = |template: &str| prepare_tex_from_str(template, &(), Some(&helpers));
+
+        let year = chrono::Utc::now().format("%Y").to_string();
+        assert_eq!(render(r#"{{now format="%Y" utc=true}}"#).unwrap(), year);
+        assert_eq!(
+            render(r#"{{now format="%Y-%m-%d"}}"#).unwrap(),
+            chrono::Local::now().format("%Y-%m-%d").to_string()
+        );
+        assert!(render("{{now}}").unwrap().ends_with(&year));
+        assert!(render(r#"{{now format="%Q"}}"#).is_err());
+    }
+
+    #[cfg(feature = "qr")]
+    #[test]
+    fn test_qrcode() {
+        let mut data = HashMap::new();
+        data.insert("id", "A-1234");
+
+        let output = prepare_tex_from_str(
+            r#"{{qrcode id size="2cm"}}"#,
+            &data,
+            Some(&default_helpers()),
+        )
+        .unwrap();
+
+        // the shortest QR code version has 21 × 21 modules and a dark top left finder pattern
+        assert!(output.contains(r"\dimen0=\dimexpr 2cm/21\relax"));
+        assert_eq!(output.matches(r"\hbox{").count(), 21);
+        assert!(output.contains(r"\hbox{\vrule width 7\dimen0 height \dimen0 "));
+
+        for size in ["", r"2cm}\input{x"] {
+            let template = format!(r#"{{{{qrcode id size="{}"}}}}"#, size);
+            assert!(prepare_tex_from_str(&template, &data, Some(&default_helpers())).is_err());
+        }
+    }
+
+    #[cfg(feature = "markdown")]
+    #[test]
+    fn test_markdown() {
+        let markdown = "# Offer & terms\n\nSome **bold** and *italic* text, see \
+                        [the site](https://example.com/a_b#c).\n\n\
+                        - first_item\n- `code`\n\n1. one\n2. two\n\n> quoted";
+
+        assert_eq!(
+            markdown_to_latex(markdown),
+            "\\section*{Offer \\& terms}\n\n\
+             Some \\textbf{bold} and \\emph{italic} text, see \
+             \\href{https://example.com/a_b\\#c}{the site}.\n\n\
+             \\begin{itemize}\n\\item first\\_item\n\\item \\texttt{code}\n\\end{itemize}\n\n\
+             \\begin{enumerate}\n\\item one\n\\item two\n\\end{enumerate}\n\n\
+             \\begin{quote}\nquoted\n\n\\end{quote}"
+        );
+        assert_eq!(
+            markdown_to_latex("**unclosed _emphasis"),
+            "**unclosed \\_emphasis"
+        );
+        assert_eq!(
+            markdown_to_latex("```\nx % 2\n```\n\n    indented\n\nafter"),
+            "\\begin{verbatim}\nx % 2\n\\end{verbatim}\n\n\
+             \\begin{verbatim}\nindented\n\\end{verbatim}\n\nafter"
+        );
+        assert_eq!(
+            markdown_to_latex("```\na\n\\end{verbatim}\n\\input{x}\n```"),
+            "\\texttt{a\\newline\n\\textbackslash{}end\\{verbatim\\}\\newline\n\
+             \\textbackslash{}input\\{x\\}}"
+        );
+    }
+}
@@ -1,127 +1,5576 @@
-use handlebars::{Context, Handlebars, Helper, Output, RenderContext, RenderError};
+use encoding_rs::Encoding;
+use handlebars::template::{BlockParam, HelperTemplate, Parameter, Template, TemplateElement};
+use handlebars::{
+    BlockContext, Context, Handlebars, Helper, HelperDef, HelperResult, Output, RenderContext,
+    RenderError, Renderable, ScopedJson, TemplateError,
+};
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
 use std::error::Error;
+use std::fmt;
 use std::fs::{read_to_string, File};
-use std::io::Write;
-use std::path::Path;
-
-/// Alias for a `(String, fn(h: &Helper<'_, '_>, hb: &Handlebars<'_>, c: &Context, rc: &mut
-/// RenderContext<'_, '_>, out: &mut dyn Output) -> HelperResult)`.
-pub type HandlebarsHelper = (
-    String,
-    fn(
-        h: &Helper,
-        hb: &Handlebars,
-        c: &Context,
-        rc: &mut RenderContext,
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+#[cfg(feature = "pdf")]
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "pdf")]
+use std::thread;
+use std::time::Duration;
+#[cfg(feature = "pdf")]
+use std::time::SystemTime;
+#[cfg(feature = "pdf")]
+use tectonic::config::PersistentConfig;
+#[cfg(feature = "pdf")]
+use tectonic::driver::{OutputFormat, ProcessingSessionBuilder};
+#[cfg(feature = "pdf")]
+use tectonic::status::plain::PlainStatusBackend;
+#[cfg(feature = "pdf")]
+use tectonic::status::{ChatterLevel, MessageKind, NoopStatusBackend, StatusBackend};
+#[cfg(feature = "pdf")]
+use tectonic::unstable_opts::UnstableOptions;
+#[cfg(feature = "pdf")]
+use tectonic_bridge_core::{SecuritySettings, SecurityStance};
+
+pub mod helpers;
+
+pub use encoding_rs;
+pub use helpers::default_helpers;
+
+/// A helper registered under `name`; anything implementing `HelperDef` can be used, including
+/// `fn` helpers and closures capturing their environment.
+///
+/// Existing `("name".to_string(), function)` tuples convert with `.into()`.
+#[derive(Clone)]
+pub struct HandlebarsHelper {
+    name: String,
+    def: Arc<dyn HelperDef + Send + Sync>,
+}
+
+impl HandlebarsHelper {
+    /// Creates a helper called `name` from a `fn`, a closure or any other `HelperDef`
+    pub fn new<N: Into<String>, H: HelperDef + Send + Sync + 'static>(name: N, def: H) -> Self {
+        HandlebarsHelper {
+            name: name.into(),
+            def: Arc::new(def),
+        }
+    }
+
+    /// Returns the name the helper is registered under
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl<H: HelperDef + Send + Sync + 'static> From<(String, H)> for HandlebarsHelper {
+    fn from((name, def): (String, H)) -> Self {
+        HandlebarsHelper::new(name, def)
+    }
+}
+
+impl<H: HelperDef + Send + Sync + 'static> From<(&str, H)> for HandlebarsHelper {
+    fn from((name, def): (&str, H)) -> Self {
+        HandlebarsHelper::new(name, def)
+    }
+}
+
+impl fmt::Debug for HandlebarsHelper {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("HandlebarsHelper").field(&self.name).finish()
+    }
+}
+
+/// Lets a shared `HandlebarsHelper` be registered with several registries
+struct SharedHelper(Arc<dyn HelperDef + Send + Sync>);
+
+impl HelperDef for SharedHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        r: &'reg Handlebars<'reg>,
+        ctx: &'rc Context,
+        rc: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'reg, 'rc>, RenderError> {
+        self.0.call_inner(h, r, ctx, rc)
+    }
+
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        r: &'reg Handlebars<'reg>,
+        ctx: &'rc Context,
+        rc: &mut RenderContext<'reg, 'rc>,
         out: &mut dyn Output,
-    ) -> Result<(), RenderError>,
-);
+    ) -> HelperResult {
+        self.0.call(h, r, ctx, rc, out)
+    }
+}
+
+/// How interpolated `{{value}}` expressions are escaped; triple-stash `{{{value}}}` is never
+/// escaped
+#[derive(Clone, Copy, Debug, Default, Eq)]
+pub enum EscapeMode {
+    /// Values are written as they are
+    #[default]
+    None,
+    /// Values are escaped with `latex_escape`
+    Latex,
+    /// Values are escaped with the given function, e.g. one which also replaces domain-specific
+    /// markup
+    Custom(fn(&str) -> String),
+}
+
+impl PartialEq for EscapeMode {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (EscapeMode::None, EscapeMode::None) | (EscapeMode::Latex, EscapeMode::Latex) => true,
+            (EscapeMode::Custom(a), EscapeMode::Custom(b)) => std::ptr::fn_addr_eq(*a, *b),
+            _ => false,
+        }
+    }
+}
+
+/// What rendering does when `TemplateRecipe::output` already exists
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    /// The existing file is replaced
+    #[default]
+    Replace,
+    /// The existing file is kept and the new PDF is not written
+    Skip,
+    /// Rendering fails with `TexTmplError::OutputExists`
+    Error,
+}
+
+/// Where Tectonic loads the TeX support files (the bundle) from
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum BundleSource {
+    /// The bundle configured for Tectonic, downloaded from the network and cached on first use
+    #[default]
+    Default,
+    /// A bundle served from a custom URL, cached like the default one
+    Url(String),
+    /// A local bundle, either a zip file or an unpacked directory; the network is never used
+    Local(PathBuf),
+}
+
+/// Options for the Tectonic compilation
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TectonicOptions {
+    /// Where the bundle is loaded from
+    pub bundle: BundleSource,
+    /// Use only already cached bundle files and fail instead of downloading missing ones; has no
+    /// effect on `BundleSource::Local`
+    pub only_cached: bool,
+    /// Directory into which the files produced next to the PDF (`.aux`, `.log`, `.synctex.gz`,
+    /// ...) are written, also when the compilation fails; they are discarded when `None`
+    pub intermediates_dir: Option<PathBuf>,
+    /// Rerun TeX exactly this many times after the first pass; when `None` Tectonic reruns it
+    /// until the `.aux` files stop changing, which settles most tables of contents and `\ref`s
+    pub reruns: Option<usize>,
+    /// The `.bib` file cited by the document; its directory is searched for the files named by
+    /// `\bibliography{...}` or biblatex's `\addbibresource{...}`. Tectonic runs BibTeX (built in)
+    /// or Biber (which has to be installed) between the passes as the document requires, so the
+    /// tool is chosen by the document, e.g. with `\usepackage[backend=biber]{biblatex}`
+    pub bibliography: Option<PathBuf>,
+    /// Directory against which `\input`, `\include`, `\includegraphics` and the like resolve
+    /// relative paths; defaults to the directory of the recipe's template
+    pub working_dir: Option<PathBuf>,
+    /// Additional directories searched for files such as PNG, JPG or PDF images included with
+    /// `\includegraphics`, after `working_dir`
+    pub asset_dirs: Vec<PathBuf>,
+    /// Produce byte-for-byte identical PDFs for identical input: the build date is fixed to
+    /// `SOURCE_DATE_EPOCH`, or to the Unix epoch when it is not set, and Tectonic's deterministic
+    /// mode hides file modification times and absolute paths from the engine
+    pub reproducible: bool,
+    /// Directory in which the compilation, and the conversions of `render_png` and `render_svg`,
+    /// keep their working files, e.g. on a partition with more space than the system's temporary
+    /// directory; the files are removed afterwards unless `intermediates_dir` is set. When `None`
+    /// Tectonic keeps them in memory and the conversions use `std::env::temp_dir`
+    pub temp_dir: Option<PathBuf>,
+    /// Settings of the TeX engine, such as shell escape; the defaults are the safe ones
+    pub engine_options: EngineOptions,
+    /// Fail with `TexTmplError::Warnings` when the TeX log of a successful compilation contains
+    /// warnings, e.g. to enforce a clean layout in CI
+    pub warnings_as_errors: bool,
+    /// Text a line of the TeX log has to contain to count for `warnings_as_errors`, e.g.
+    /// `Overfull \hbox`; when `None` overfull and underfull boxes and every LaTeX, class or package
+    /// warning count
+    pub warning_pattern: Option<String>,
+    /// How much of Tectonic's progress is printed to stderr; nothing by default
+    pub verbosity: Verbosity,
+}
+
+/// How much Tectonic prints to stderr while compiling; the messages reach the status of functions
+/// such as `render_pdf_verbose` regardless
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Verbosity {
+    /// Print nothing, e.g. in production
+    #[default]
+    Quiet,
+    /// Print warnings and errors, together with the TeX log of a failed compilation
+    Warnings,
+    /// Print every note on the progress as well, e.g. while debugging a template
+    Verbose,
+}
+
+#[cfg(feature = "pdf")]
+impl Verbosity {
+    /// Returns the backend printing the messages of this verbosity, `None` for `Quiet`
+    fn printer(self) -> Option<PlainStatusBackend> {
+        let chatter = match self {
+            Verbosity::Quiet => return None,
+            Verbosity::Warnings => ChatterLevel::Minimal,
+            Verbosity::Verbose => ChatterLevel::Normal,
+        };
+        let mut printer = PlainStatusBackend::new(chatter);
+        printer.always_stderr(true);
+        Some(printer)
+    }
+}
+
+/// Settings passed to the TeX engine, as Tectonic's command line options would; the defaults are
+/// the safest configuration and suit untrusted templates
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EngineOptions {
+    /// Let `\write18` run shell commands, in the compilation's working directory (the template's
+    /// directory by default); anyone controlling the template or unescaped data can then run any
+    /// command, so never enable it for untrusted input
+    pub shell_escape: bool,
+    /// Format the primary input is processed with, e.g. `plain`; `latex` when `None`
+    pub format: Option<String>,
+}
+
+#[cfg(feature = "pdf")]
+impl TectonicOptions {
+    /// Returns the directories searched for files besides the bundle
+    fn search_paths(&self) -> Vec<PathBuf> {
+        let mut paths = self.asset_dirs.clone();
+        if let Some(bibliography) = &self.bibliography {
+            match bibliography.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => paths.push(dir.to_path_buf()),
+                _ => paths.push(PathBuf::from(".")),
+            }
+        }
+        paths
+    }
+}
+
+/// Document information written into the generated PDF and shown in a viewer's properties; the
+/// values are plain text and need no escaping
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PdfMetadata {
+    /// Title of the document
+    pub title: Option<String>,
+    /// Author of the document
+    pub author: Option<String>,
+    /// Subject of the document
+    pub subject: Option<String>,
+    /// Keywords, written joined by `, `
+    pub keywords: Vec<String>,
+}
+
+/// A function adjusting the Handlebars registry of a `TemplateRecipe`, see
+/// `TemplateRecipe::configure_registry`
+pub type RegistryHook = Arc<dyn Fn(&mut Handlebars<'static>) + Send + Sync>;
+
+/// A recipe for `render_pdf` which specifies an input template path, an output PDF path, data in
+/// form of mapping (`Serialize`able) and the options for rendering the template; the output path
+/// is only needed by the functions writing a PDF
+///
+/// Recipes are put together with `TemplateRecipe::builder()`. The struct is `#[non_exhaustive]`,
+/// so adding an option does not break existing code; the fields of a built recipe can still be
+/// read and changed.
+#[derive(Clone)]
+#[non_exhaustive]
+pub struct TemplateRecipe<'a, T: serde::Serialize> {
+    /// Path of the main template
+    pub template: &'a Path,
+    /// Path of the PDF to write; the functions writing it fail with
+    /// `TexTmplError::MissingField("output")` when it is `None`
+    pub output: Option<&'a Path>,
+    /// Data the template is rendered with
+    pub data: &'a T,
+    /// Additional helpers to register, either owned (`Some(default_helpers().into())`) or a set
+    /// borrowed by many recipes without copying it (`Some(Cow::Borrowed(&helpers))`)
+    pub helpers: Option<Cow<'a, [HandlebarsHelper]>>,
+    /// How interpolated values are escaped
+    pub escape: EscapeMode,
+    /// Partials as `(name, path)` pairs, usable as `{{> name}}` in the template
+    pub partials: Option<Vec<(String, PathBuf)>>,
+    /// Path of a layout into which the rendered template is inserted at `{{> @partial-block}}`
+    pub layout: Option<&'a Path>,
+    /// Custom `(open, close)` delimiters used instead of `{{` and `}}` in the template, its
+    /// partials and layout, e.g. `("<<", ">>")`; raw output is then written as `<<{value}>>`
+    pub delimiters: Option<(String, String)>,
+    /// Fail with `TexTmplError::Render` when the template references a missing value instead of
+    /// rendering it as an empty string
+    pub strict: bool,
+    /// Check the rendered TeX with `validate_tex` and fail with `TexTmplError::Validation` before
+    /// it is handed to Tectonic
+    pub validate: bool,
+    /// Options for the Tectonic compilation, e.g. where the bundle is loaded from
+    pub tectonic: TectonicOptions,
+    /// Document information written into the PDF's info dictionary
+    pub metadata: Option<PdfMetadata>,
+    /// Called with the Handlebars registry after the helpers are registered and before the
+    /// partials and the template are, e.g. to register a `HelperDef` or enable `dev_mode`
+    pub configure_registry: Option<RegistryHook>,
+    /// Fail with `TexTmplError::Validation` when the template contains no Handlebars expressions,
+    /// which usually means the recipe points at the wrong, static file
+    pub require_placeholders: bool,
+    /// Character encoding of the template, its partials and layout, e.g. `encoding_rs::WINDOWS_1252`
+    /// for Latin-1 files; UTF-8 when `None`
+    pub encoding: Option<&'static Encoding>,
+    /// Name the template is registered under, which Handlebars error messages refer to;
+    /// `tex_template` when `None`
+    pub template_name: Option<String>,
+    /// LaTeX packages to load with `\usepackage` right after `\documentclass`, e.g. `hyperref`
+    /// for `{{href}}`; packages the document already loads are skipped
+    pub packages: Vec<String>,
+    /// Create the missing parent directories of `output` before writing it
+    pub create_dirs: bool,
+    /// What to do when `output` already exists; functions which only write the PDF, such as
+    /// `render_pdf`, check it before rendering, so a skipped recipe is not compiled at all
+    pub overwrite: OverwritePolicy,
+}
+
+impl<'a, T: serde::Serialize> TemplateRecipe<'a, T> {
+    /// Returns a `TemplateRecipeBuilder` with every option at its default
+    pub fn builder() -> TemplateRecipeBuilder<'a, T> {
+        TemplateRecipeBuilder {
+            template: None,
+            output: None,
+            data: None,
+            helpers: None,
+            escape: EscapeMode::None,
+            partials: None,
+            layout: None,
+            delimiters: None,
+            strict: false,
+            validate: false,
+            tectonic: TectonicOptions::default(),
+            metadata: None,
+            configure_registry: None,
+            require_placeholders: false,
+            encoding: None,
+            template_name: None,
+            packages: Vec::new(),
+            create_dirs: false,
+            overwrite: OverwritePolicy::Replace,
+        }
+    }
+
+    /// Returns `tectonic` with `working_dir` defaulting to the directory of `template`
+    #[cfg(feature = "pdf")]
+    fn tectonic_options(&self) -> TectonicOptions {
+        TectonicOptions {
+            working_dir: self
+                .tectonic
+                .working_dir
+                .clone()
+                .or_else(|| self.template.parent().map(Path::to_path_buf)),
+            ..self.tectonic.clone()
+        }
+    }
+
+    /// Returns `output` or `TexTmplError::MissingField` if it is not set
+    #[cfg(feature = "pdf")]
+    fn output_path(&self) -> Result<&'a Path, TexTmplError> {
+        self.output.ok_or(TexTmplError::MissingField("output"))
+    }
+
+    /// Returns whether the existing `output` is to be kept under `OverwritePolicy::Skip`, or
+    /// `TexTmplError::OutputExists` under `OverwritePolicy::Error`; fails as `output_path` when
+    /// there is no `output`
+    #[cfg(feature = "pdf")]
+    fn keep_output(&self) -> Result<bool, TexTmplError> {
+        let output = self.output_path()?;
+        match self.overwrite {
+            OverwritePolicy::Replace => Ok(false),
+            _ if !output.exists() => Ok(false),
+            OverwritePolicy::Skip => Ok(true),
+            OverwritePolicy::Error => Err(TexTmplError::OutputExists(output.to_path_buf())),
+        }
+    }
+
+    /// Writes `content` into `output` as `overwrite` allows, see `output_dir`
+    #[cfg(feature = "pdf")]
+    fn write_output(&self, content: &[u8]) -> Result<(), TexTmplError> {
+        if self.keep_output()? {
+            return Ok(());
+        }
+
+        write_file(self.output_dir()?, content)
+    }
+
+    /// Returns `output` once its directory exists, creating the directory if `create_dirs` is
+    /// set; otherwise a missing directory fails with an error naming it
+    #[cfg(feature = "pdf")]
+    fn output_dir(&self) -> Result<&'a Path, TexTmplError> {
+        let output = self.output_path()?;
+        let output_error = |e| TexTmplError::OutputWrite(output.to_path_buf(), e);
+
+        if let Some(dir) = output.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            if self.create_dirs {
+                std::fs::create_dir_all(dir).map_err(output_error)?;
+            } else if !dir.is_dir() {
+                return Err(output_error(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("directory {} does not exist", dir.display()),
+                )));
+            }
+        }
+
+        Ok(output)
+    }
+
+    /// Applies the recipe's changes to the compiled PDF
+    #[cfg(feature = "pdf")]
+    fn finish_pdf(&self, pdf: Vec<u8>) -> Result<Vec<u8>, TexTmplError> {
+        match &self.metadata {
+            Some(metadata) => set_metadata(pdf, metadata),
+            None => Ok(pdf),
+        }
+    }
+}
+
+/// A builder for `TemplateRecipe`; `template` and `data` are required, everything else falls back
+/// to its default
+pub struct TemplateRecipeBuilder<'a, T: serde::Serialize> {
+    template: Option<&'a Path>,
+    output: Option<&'a Path>,
+    data: Option<&'a T>,
+    helpers: Option<Cow<'a, [HandlebarsHelper]>>,
+    escape: EscapeMode,
+    partials: Option<Vec<(String, PathBuf)>>,
+    layout: Option<&'a Path>,
+    delimiters: Option<(String, String)>,
+    strict: bool,
+    validate: bool,
+    tectonic: TectonicOptions,
+    metadata: Option<PdfMetadata>,
+    configure_registry: Option<RegistryHook>,
+    require_placeholders: bool,
+    encoding: Option<&'static Encoding>,
+    template_name: Option<String>,
+    packages: Vec<String>,
+    create_dirs: bool,
+    overwrite: OverwritePolicy,
+}
+
+impl<'a, T: serde::Serialize> TemplateRecipeBuilder<'a, T> {
+    /// Sets the path of the main template
+    pub fn template(mut self, template: &'a Path) -> Self {
+        self.template = Some(template);
+        self
+    }
+
+    /// Sets the path of the PDF to write
+    pub fn output(mut self, output: &'a Path) -> Self {
+        self.output = Some(output);
+        self
+    }
+
+    /// Sets the data the template is rendered with
+    pub fn data(mut self, data: &'a T) -> Self {
+        self.data = Some(data);
+        self
+    }
+
+    /// Adds a helper, either a `HandlebarsHelper` or a `(name, helper)` tuple
+    pub fn helper<H: Into<HandlebarsHelper>>(mut self, helper: H) -> Self {
+        self.helpers
+            .get_or_insert_with(Cow::default)
+            .to_mut()
+            .push(helper.into());
+        self
+    }
+
+    /// Adds several helpers, e.g. `default_helpers()`
+    pub fn helpers<I: IntoIterator<Item = HandlebarsHelper>>(mut self, helpers: I) -> Self {
+        self.helpers
+            .get_or_insert_with(Cow::default)
+            .to_mut()
+            .extend(helpers);
+        self
+    }
+
+    /// Uses a set of helpers shared by many recipes without copying it; helpers added to the
+    /// recipe before or after are combined with a copy of the set
+    pub fn shared_helpers(mut self, helpers: &'a [HandlebarsHelper]) -> Self {
+        match &mut self.helpers {
+            Some(own) => own.to_mut().extend_from_slice(helpers),
+            None => self.helpers = Some(Cow::Borrowed(helpers)),
+        }
+        self
+    }
+
+    /// Sets how interpolated values are escaped
+    pub fn escape(mut self, escape: EscapeMode) -> Self {
+        self.escape = escape;
+        self
+    }
+
+    /// Adds a partial usable as `{{> name}}`
+    pub fn partial<N: Into<String>, P: Into<PathBuf>>(mut self, name: N, path: P) -> Self {
+        self.partials
+            .get_or_insert_with(Vec::new)
+            .push((name.into(), path.into()));
+        self
+    }
+
+    /// Sets the path of the layout
+    pub fn layout(mut self, layout: &'a Path) -> Self {
+        self.layout = Some(layout);
+        self
+    }
+
+    /// Sets custom delimiters used instead of `{{` and `}}`
+    pub fn delimiters<O: Into<String>, C: Into<String>>(mut self, open: O, close: C) -> Self {
+        self.delimiters = Some((open.into(), close.into()));
+        self
+    }
+
+    /// Sets whether missing values fail the rendering
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Sets whether the rendered TeX is checked with `validate_tex`
+    pub fn validate(mut self, validate: bool) -> Self {
+        self.validate = validate;
+        self
+    }
+
+    /// Sets the options for the Tectonic compilation
+    pub fn tectonic(mut self, tectonic: TectonicOptions) -> Self {
+        self.tectonic = tectonic;
+        self
+    }
+
+    /// Sets the document information written into the PDF
+    pub fn metadata(mut self, metadata: PdfMetadata) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    /// Sets the function called with the Handlebars registry before the template is registered
+    pub fn configure_registry<F>(mut self, configure: F) -> Self
+    where
+        F: Fn(&mut Handlebars<'static>) + Send + Sync + 'static,
+    {
+        self.configure_registry = Some(Arc::new(configure));
+        self
+    }
+
+    /// Sets whether a template without Handlebars expressions fails the rendering
+    pub fn require_placeholders(mut self, require_placeholders: bool) -> Self {
+        self.require_placeholders = require_placeholders;
+        self
+    }
+
+    /// Sets the character encoding of the template files
+    pub fn encoding(mut self, encoding: &'static Encoding) -> Self {
+        self.encoding = Some(encoding);
+        self
+    }
+
+    /// Sets the name the template is registered under
+    pub fn template_name<N: Into<String>>(mut self, name: N) -> Self {
+        self.template_name = Some(name.into());
+        self
+    }
+
+    /// Adds a LaTeX package loaded right after `\documentclass`
+    pub fn package<P: Into<String>>(mut self, package: P) -> Self {
+        self.packages.push(package.into());
+        self
+    }
+
+    /// Sets whether the missing parent directories of the output are created
+    pub fn create_dirs(mut self, create_dirs: bool) -> Self {
+        self.create_dirs = create_dirs;
+        self
+    }
+
+    /// Sets what happens when the output already exists
+    pub fn overwrite(mut self, overwrite: OverwritePolicy) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// Returns the `TemplateRecipe` or `TexTmplError::MissingField` naming the first required
+    /// field which was not set
+    pub fn build(self) -> Result<TemplateRecipe<'a, T>, TexTmplError> {
+        Ok(TemplateRecipe {
+            template: self
+                .template
+                .ok_or(TexTmplError::MissingField("template"))?,
+            output: self.output,
+            data: self.data.ok_or(TexTmplError::MissingField("data"))?,
+            helpers: self.helpers,
+            escape: self.escape,
+            partials: self.partials,
+            layout: self.layout,
+            delimiters: self.delimiters,
+            strict: self.strict,
+            validate: self.validate,
+            tectonic: self.tectonic,
+            metadata: self.metadata,
+            configure_registry: self.configure_registry,
+            require_placeholders: self.require_placeholders,
+            encoding: self.encoding,
+            template_name: self.template_name,
+            packages: self.packages,
+            create_dirs: self.create_dirs,
+            overwrite: self.overwrite,
+        })
+    }
+}
+
+/// Errors returned while preparing TeX or rendering a PDF
+#[derive(Debug)]
+pub enum TexTmplError {
+    /// The template file could not be read
+    TemplateRead(PathBuf, io::Error),
+    /// The template could not be read from the supplied reader
+    TemplateStream(io::Error),
+    /// The template could not be parsed by Handlebars
+    Template(Box<TemplateError>),
+    /// Handlebars failed to render the template with the given data
+    Render(Box<RenderError>),
+    /// Tectonic failed to compile the rendered TeX; when Tectonic names the failing line of the
+    /// rendered TeX, `line` is its one-based number, `message` the TeX error and `snippet` the
+    /// lines around it, numbered and with the failing one marked by `>`
+    Compile {
+        line: Option<usize>,
+        message: String,
+        snippet: Option<String>,
+    },
+    /// An output (PDF or TeX) file could not be written
+    OutputWrite(PathBuf, io::Error),
+    /// The output file exists and `TemplateRecipe::overwrite` is `OverwritePolicy::Error`
+    OutputExists(PathBuf),
+    /// The output could not be written into the supplied writer
+    OutputStream(io::Error),
+    /// The produced PDF could not be parsed
+    #[cfg(feature = "pdf")]
+    Pdf(lopdf::Error),
+    /// The named partial could not be read or parsed
+    Partial(String, Box<TexTmplError>),
+    /// The compilation did not finish within the given time
+    Timeout(Duration),
+    /// A required field was not set on `TemplateRecipeBuilder`, or a PDF was to be written for a
+    /// `TemplateRecipe` without `output`
+    MissingField(&'static str),
+    /// The rendered TeX failed `validate_tex`, or the template has no placeholders although
+    /// `TemplateRecipe::require_placeholders` is set; holds the character offset and the problem
+    /// found there
+    Validation(usize, String),
+    /// Cannot read or parse the data file
+    Data(PathBuf, Box<dyn Error + Send + Sync>),
+    /// Cannot rasterize the PDF into PNG images or convert it into SVG
+    Rasterize(String),
+    /// The rendered TeX failed to compile in `render_pdf_with_tex`; holds the TeX and the error
+    WithTex(String, Box<TexTmplError>),
+    /// Two of the given helpers have the same name, or one has the name of a built-in helper such
+    /// as `if` or `escape`, so one would silently replace the other
+    DuplicateHelper(String),
+    /// The TeX compiled, but its log contains the given warnings and
+    /// `TectonicOptions::warnings_as_errors` is set
+    Warnings(Vec<String>),
+}
+
+impl fmt::Display for TexTmplError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TexTmplError::TemplateRead(path, e) => {
+                write!(f, "Cannot read template file {}: {}", path.display(), e)
+            }
+            TexTmplError::TemplateStream(e) => write!(f, "Cannot read template: {}", e),
+            TexTmplError::Template(e) => write!(f, "Cannot parse template: {}", e),
+            TexTmplError::Render(e) => write!(f, "Cannot render template: {}", e),
+            TexTmplError::Compile {
+                line: Some(line),
+                message,
+                ..
+            } => write!(f, "Cannot compile TeX at line {}: {}", line, message),
+            TexTmplError::Compile { message, .. } => write!(f, "Cannot compile TeX: {}", message),
+            TexTmplError::OutputWrite(path, e) => {
+                write!(f, "Cannot write output file {}: {}", path.display(), e)
+            }
+            TexTmplError::OutputExists(path) => {
+                write!(f, "Output file {} already exists", path.display())
+            }
+            TexTmplError::OutputStream(e) => write!(f, "Cannot write output: {}", e),
+            #[cfg(feature = "pdf")]
+            TexTmplError::Pdf(e) => write!(f, "Cannot parse PDF: {}", e),
+            TexTmplError::Partial(name, e) => write!(f, "Cannot load partial {}: {}", name, e),
+            TexTmplError::Timeout(timeout) => {
+                write!(f, "Compilation did not finish within {:?}", timeout)
+            }
+            TexTmplError::MissingField(field) => {
+                write!(f, "Missing required recipe field {}", field)
+            }
+            TexTmplError::Validation(offset, message) => {
+                write!(f, "Invalid TeX at character {}: {}", offset, message)
+            }
+            TexTmplError::Data(path, e) => {
+                write!(f, "Cannot load data file {}: {}", path.display(), e)
+            }
+            TexTmplError::Rasterize(msg) => write!(f, "Cannot convert PDF into images: {}", msg),
+            TexTmplError::WithTex(_, e) => write!(f, "{}", e),
+            TexTmplError::DuplicateHelper(name) => {
+                write!(f, "Helper {} is registered more than once", name)
+            }
+            TexTmplError::Warnings(warnings) => {
+                write!(f, "TeX compiled with warnings: {}", warnings.join("; "))
+            }
+        }
+    }
+}
+
+impl Error for TexTmplError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TexTmplError::TemplateRead(_, e) | TexTmplError::OutputWrite(_, e) => Some(e),
+            TexTmplError::TemplateStream(e) | TexTmplError::OutputStream(e) => Some(e),
+            #[cfg(feature = "pdf")]
+            TexTmplError::Pdf(e) => Some(e),
+            TexTmplError::Partial(_, e) | TexTmplError::WithTex(_, e) => Some(e.as_ref()),
+            TexTmplError::Data(_, e) => Some(e.as_ref()),
+            TexTmplError::Template(e) => Some(e.as_ref()),
+            TexTmplError::Render(e) => Some(e.as_ref()),
+            TexTmplError::Compile { .. }
+            | TexTmplError::Timeout(_)
+            | TexTmplError::MissingField(_)
+            | TexTmplError::Validation(..)
+            | TexTmplError::Rasterize(_)
+            | TexTmplError::DuplicateHelper(_)
+            | TexTmplError::OutputExists(_)
+            | TexTmplError::Warnings(_) => None,
+        }
+    }
+}
+
+impl TexTmplError {
+    /// Returns a `TexTmplError::Compile` without a line
+    #[cfg(feature = "pdf")]
+    fn compile<M: Into<String>>(message: M) -> Self {
+        TexTmplError::Compile {
+            line: None,
+            message: message.into(),
+            snippet: None,
+        }
+    }
+}
+
+impl From<TemplateError> for TexTmplError {
+    fn from(e: TemplateError) -> Self {
+        TexTmplError::Template(Box::new(e))
+    }
+}
+
+impl From<RenderError> for TexTmplError {
+    fn from(e: RenderError) -> Self {
+        TexTmplError::Render(Box::new(e))
+    }
+}
+
+#[cfg(feature = "pdf")]
+impl From<tectonic::Error> for TexTmplError {
+    fn from(e: tectonic::Error) -> Self {
+        let mut msg = e.to_string();
+        let mut source = e.source();
+        while let Some(s) = source {
+            msg.push_str(&format!(": {}", s));
+            source = s.source();
+        }
+        TexTmplError::compile(msg)
+    }
+}
+
+/// Summary of a PDF rendered by `render_pdf_reported`
+#[cfg(feature = "pdf")]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RenderReport {
+    /// Number of pages in the PDF
+    pub pages: usize,
+    /// Size of the PDF in bytes
+    pub bytes: usize,
+    /// Warnings reported by Tectonic during the compilation
+    pub warnings: Vec<String>,
+}
+
+/// A `StatusBackend` which keeps the warnings reported by Tectonic
+#[cfg(feature = "pdf")]
+#[derive(Default)]
+struct WarningCollector {
+    warnings: Vec<String>,
+}
+
+#[cfg(feature = "pdf")]
+impl StatusBackend for WarningCollector {
+    fn report(&mut self, kind: MessageKind, args: fmt::Arguments, _err: Option<&anyhow::Error>) {
+        if kind == MessageKind::Warning {
+            self.warnings.push(args.to_string());
+        }
+    }
+
+    fn dump_error_logs(&mut self, _output: &[u8]) {}
+}
+
+/// A `StatusBackend` which keeps every message reported by Tectonic, prefixed with its kind, and
+/// the lines of the TeX log dumped on failure
+#[cfg(feature = "pdf")]
+#[derive(Default)]
+struct LogCollector {
+    messages: Vec<String>,
+}
+
+#[cfg(feature = "pdf")]
+impl StatusBackend for LogCollector {
+    fn report(&mut self, kind: MessageKind, args: fmt::Arguments, err: Option<&anyhow::Error>) {
+        let prefix = match kind {
+            MessageKind::Note => "note",
+            MessageKind::Warning => "warning",
+            MessageKind::Error => "error",
+        };
+        let mut message = format!("{}: {}", prefix, args);
+        if let Some(err) = err {
+            for cause in err.chain() {
+                message.push_str(&format!(": {}", cause));
+            }
+        }
+        self.messages.push(message);
+    }
+
+    fn dump_error_logs(&mut self, output: &[u8]) {
+        self.messages
+            .extend(String::from_utf8_lossy(output).lines().map(str::to_string));
+    }
+}
+
+/// A `StatusBackend` which passes everything on to `inner` and prints it with `printer`, if any
+#[cfg(feature = "pdf")]
+struct Echo<'a> {
+    inner: &'a mut dyn StatusBackend,
+    printer: Option<PlainStatusBackend>,
+}
+
+#[cfg(feature = "pdf")]
+impl StatusBackend for Echo<'_> {
+    fn report(&mut self, kind: MessageKind, args: fmt::Arguments, err: Option<&anyhow::Error>) {
+        if let Some(printer) = &mut self.printer {
+            printer.report(kind, args, err);
+        }
+        self.inner.report(kind, args, err);
+    }
+
+    fn dump_error_logs(&mut self, output: &[u8]) {
+        if let Some(printer) = &mut self.printer {
+            printer.dump_error_logs(output);
+        }
+        self.inner.dump_error_logs(output);
+    }
+}
+
+/// A `StatusBackend` which passes everything on to `inner` and keeps the line and message of the
+/// first TeX error in the primary input, as reported (`texput.tex:12: Undefined control
+/// sequence.`) or as found in the dumped log (`! Undefined control sequence.` ... `l.12 ...`)
+#[cfg(feature = "pdf")]
+struct ErrorLocator<'a> {
+    inner: &'a mut dyn StatusBackend,
+    error: Option<(usize, String)>,
+}
+
+#[cfg(feature = "pdf")]
+impl StatusBackend for ErrorLocator<'_> {
+    fn report(&mut self, kind: MessageKind, args: fmt::Arguments, err: Option<&anyhow::Error>) {
+        if kind == MessageKind::Error && self.error.is_none() {
+            let message = args.to_string();
+            self.error = message
+                .split_once("texput.tex:")
+                .and_then(|(_, rest)| rest.split_once(": "))
+                .and_then(|(line, message)| Some((line.parse().ok()?, message.to_string())));
+        }
+        self.inner.report(kind, args, err);
+    }
+
+    fn dump_error_logs(&mut self, output: &[u8]) {
+        if self.error.is_none() {
+            let log = String::from_utf8_lossy(output);
+            let mut message = None;
+            for log_line in log.lines() {
+                if let Some(m) = log_line.strip_prefix("! ") {
+                    message.get_or_insert_with(|| m.to_string());
+                } else if let (Some(m), Some(rest)) = (&message, log_line.strip_prefix("l.")) {
+                    let digits: String = rest.chars().take_while(char::is_ascii_digit).collect();
+                    if let Ok(line) = digits.parse() {
+                        self.error = Some((line, m.clone()));
+                        break;
+                    }
+                }
+            }
+        }
+        self.inner.dump_error_logs(output);
+    }
+}
+
+/// Returns the lines of `tex` around the one-based `line`, each prefixed with its number and
+/// `line` itself marked with `>`
+#[cfg(feature = "pdf")]
+fn tex_snippet(tex: &str, line: usize) -> String {
+    tex.lines()
+        .enumerate()
+        .map(|(index, text)| (index + 1, text))
+        .skip(line.saturating_sub(3))
+        .take_while(|(number, _)| *number <= line + 2)
+        .map(|(number, text)| {
+            let marker = if number == line { '>' } else { ' ' };
+            format!("{}{:>5} | {}", marker, number, text)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Tectonic configuration which is loaded once and reused for every compilation
+///
+/// Loading the configuration and locating the bundle cache and the format files is done once in
+/// `TexEngine::new`, so in a service a single engine, e.g. in an `Arc<TexEngine>`, can be shared
+/// by every thread; its methods take `&self` and are safe to call concurrently. Tectonic still
+/// runs its TeX engine under a process-wide lock, so the compilations themselves take turns.
+#[cfg(feature = "pdf")]
+pub struct TexEngine {
+    config: PersistentConfig,
+    format_cache_path: PathBuf,
+}
+
+#[cfg(feature = "pdf")]
+impl TexEngine {
+    /// Loads the Tectonic configuration
+    pub fn new() -> Result<TexEngine, TexTmplError> {
+        let config = PersistentConfig::open(false)?;
+        let format_cache_path = config.format_cache_path()?;
+
+        Ok(TexEngine {
+            config,
+            format_cache_path,
+        })
+    }
+
+    /// Compiles `tex` into PDF bytes the same way `tectonic::latex_to_pdf` does, but loads the
+    /// bundle as `options` say and reports the status messages into `status`
+    fn compile_pdf(
+        &self,
+        tex: &str,
+        options: &TectonicOptions,
+        status: &mut dyn StatusBackend,
+    ) -> Result<Vec<u8>, TexTmplError> {
+        self.compile(tex, options, OutputFormat::Pdf, status)
+    }
+
+    /// Compiles `tex` like `compile_pdf`, but into `format`, i.e. PDF or XDV bytes
+    fn compile(
+        &self,
+        tex: &str,
+        options: &TectonicOptions,
+        format: OutputFormat,
+        status: &mut dyn StatusBackend,
+    ) -> Result<Vec<u8>, TexTmplError> {
+        let output_name = match format {
+            OutputFormat::Xdv => "texput.xdv",
+            _ => "texput.pdf",
+        };
+        let work_dir = match &options.temp_dir {
+            Some(parent) => Some(TempDir::new(parent, options.intermediates_dir.is_some())?),
+            None => None,
+        };
+        let mut echo = Echo {
+            inner: status,
+            printer: options.verbosity.printer(),
+        };
+        let status: &mut dyn StatusBackend = &mut echo;
+
+        let bundle = match &options.bundle {
+            BundleSource::Default => self.config.default_bundle(options.only_cached, status)?,
+            BundleSource::Url(url) => {
+                self.config
+                    .make_cached_url_provider(url, options.only_cached, None, status)?
+            }
+            BundleSource::Local(path) => {
+                self.config.make_local_file_provider(path.clone(), status)?
+            }
+        };
+
+        // Tectonic ignores extra search paths and shell escape unless insecure features may be
+        // allowed
+        let search_paths = options.search_paths();
+        let shell_escape = options.engine_options.shell_escape;
+        let mut sb = if search_paths.is_empty() && !shell_escape {
+            ProcessingSessionBuilder::default()
+        } else {
+            ProcessingSessionBuilder::new_with_security(SecuritySettings::new(
+                SecurityStance::MaybeAllowInsecures,
+            ))
+        };
+        sb.bundle(bundle)
+            .unstables(UnstableOptions {
+                extra_search_paths: search_paths,
+                deterministic_mode: options.reproducible,
+                ..UnstableOptions::default()
+            })
+            .primary_input_buffer(tex.as_bytes())
+            .tex_input_name("texput.tex")
+            .format_name(options.engine_options.format.as_deref().unwrap_or("latex"))
+            .format_cache_path(&self.format_cache_path)
+            .keep_logs(options.intermediates_dir.is_some() || options.warnings_as_errors)
+            .keep_intermediates(options.intermediates_dir.is_some())
+            .synctex(options.intermediates_dir.is_some())
+            .print_stdout(false)
+            .output_format(format);
+        match &work_dir {
+            Some(dir) => sb.output_dir(&dir.path),
+            None => sb.do_not_write_output_files(),
+        };
+        if let Some(reruns) = options.reruns {
+            sb.reruns(reruns);
+        }
+        if let Some(working_dir) = &options.working_dir {
+            sb.filesystem_root(working_dir);
+        }
+        match (shell_escape, &options.working_dir) {
+            (false, _) => sb.shell_escape_disabled(),
+            (true, Some(working_dir)) => sb.shell_escape_with_work_dir(working_dir),
+            (true, None) => sb.shell_escape_with_temp_dir(),
+        };
+        if options.reproducible {
+            sb.build_date(source_date_epoch());
+        }
+
+        let mut status = ErrorLocator {
+            inner: status,
+            error: None,
+        };
+        let mut session = sb.create(&mut status)?;
+        let result = session.run(&mut status);
+        let mut files = session.into_file_data();
+
+        if let Some(dir) = &options.intermediates_dir {
+            for (name, file) in files.iter().filter(|(name, _)| *name != output_name) {
+                write_file(&dir.join(name), &file.data)?;
+            }
+        }
+        if let Err(e) = result {
+            return Err(match status.error {
+                Some((line, message)) => TexTmplError::Compile {
+                    line: Some(line),
+                    message,
+                    snippet: Some(tex_snippet(tex, line)),
+                },
+                None => e.into(),
+            });
+        }
+
+        if options.warnings_as_errors {
+            let log = files
+                .get("texput.log")
+                .map(|file| String::from_utf8_lossy(&file.data))
+                .unwrap_or_default();
+            let warnings = log_warnings(&log, options.warning_pattern.as_deref());
+            if !warnings.is_empty() {
+                return Err(TexTmplError::Warnings(warnings));
+            }
+        }
+
+        match files.remove(output_name) {
+            Some(file) => Ok(file.data),
+            None => Err(TexTmplError::compile(format!(
+                "LaTeX didn't report failure, but no {} was created",
+                output_name
+            ))),
+        }
+    }
+}
+
+/// Returns the lines of the TeX `log` containing `pattern`, or without one the lines starting an
+/// overfull or underfull box report or a LaTeX, class or package warning
+#[cfg(feature = "pdf")]
+fn log_warnings(log: &str, pattern: Option<&str>) -> Vec<String> {
+    log.lines()
+        .filter(|line| match pattern {
+            Some(pattern) => line.contains(pattern),
+            None => {
+                line.starts_with("Overfull \\")
+                    || line.starts_with("Underfull \\")
+                    || line.contains(" Warning: ")
+            }
+        })
+        .map(|line| line.trim().to_string())
+        .collect()
+}
+
+#[cfg(feature = "pdf")]
+impl TexEngine {
+    /// Compiles `tex` rendered from `recipe` with the recipe's options into its final PDF bytes
+    fn compile_recipe<T: serde::Serialize>(
+        &self,
+        tex: &str,
+        recipe: &TemplateRecipe<T>,
+        status: &mut dyn StatusBackend,
+    ) -> Result<Vec<u8>, TexTmplError> {
+        let pdf_data = self.compile_pdf(tex, &recipe.tectonic_options(), status)?;
+
+        recipe.finish_pdf(pdf_data)
+    }
+
+    /// Renders `TemplateRecipe` into PDF bytes like `render_pdf`, without writing `recipe.output`
+    pub fn render_pdf_to_bytes<T: serde::Serialize>(
+        &self,
+        recipe: &TemplateRecipe<T>,
+    ) -> Result<Vec<u8>, TexTmplError> {
+        let tex = prepare_tex(recipe)?;
+
+        self.compile_recipe(&tex, recipe, &mut NoopStatusBackend::default())
+    }
+
+    /// Outputs PDF from `TemplateRecipe` like the free `render_pdf`, with this engine
+    pub fn render_pdf<T: serde::Serialize>(
+        &self,
+        recipe: &TemplateRecipe<T>,
+    ) -> Result<(), TexTmplError> {
+        if recipe.keep_output()? {
+            return Ok(());
+        }
+        let pdf_data = self.render_pdf_to_bytes(recipe)?;
+
+        recipe.write_output(&pdf_data)
+    }
+}
+
+/// A newly created, uniquely named directory which is removed with its contents when dropped,
+/// unless `keep` is set
+#[cfg(feature = "pdf")]
+struct TempDir {
+    path: PathBuf,
+    keep: bool,
+}
+
+#[cfg(feature = "pdf")]
+impl TempDir {
+    /// Creates the directory, and `parent` if it is missing
+    fn new(parent: &Path, keep: bool) -> Result<TempDir, TexTmplError> {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static DIRS: AtomicUsize = AtomicUsize::new(0);
+
+        let path = parent.join(format!(
+            "tex_tmpl_{}_{}",
+            std::process::id(),
+            DIRS.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&path).map_err(|e| TexTmplError::OutputWrite(path.clone(), e))?;
+
+        Ok(TempDir { path, keep })
+    }
+}
+
+#[cfg(feature = "pdf")]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        if !self.keep {
+            let _ = std::fs::remove_dir_all(&self.path);
+        }
+    }
+}
+
+/// Returns the time in `SOURCE_DATE_EPOCH`, or the Unix epoch when it is not set or invalid
+#[cfg(feature = "pdf")]
+fn source_date_epoch() -> SystemTime {
+    std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.trim().parse().ok())
+        .and_then(|epoch| SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(epoch)))
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+/// Compiles `tex` rendered from `recipe` like `TexEngine::compile_recipe` with a freshly loaded
+/// `TexEngine`
+#[cfg(feature = "pdf")]
+fn compile_recipe<T: serde::Serialize>(
+    tex: &str,
+    recipe: &TemplateRecipe<T>,
+    status: &mut dyn StatusBackend,
+) -> Result<Vec<u8>, TexTmplError> {
+    TexEngine::new()?.compile_recipe(tex, recipe, status)
+}
+
+/// Compiles `tex` into PDF bytes with a freshly loaded `TexEngine`
+#[cfg(feature = "pdf")]
+fn compile_pdf(
+    tex: &str,
+    options: &TectonicOptions,
+    status: &mut dyn StatusBackend,
+) -> Result<Vec<u8>, TexTmplError> {
+    TexEngine::new()?.compile_pdf(tex, options, status)
+}
+
+/// Returns the number of pages of `pdf`
+#[cfg(feature = "pdf")]
+fn count_pages(pdf: &[u8]) -> Result<usize, TexTmplError> {
+    let document = lopdf::Document::load_mem(pdf).map_err(TexTmplError::Pdf)?;
+
+    Ok(document.get_pages().len())
+}
+
+/// Returns `pdf` with `metadata` set in its info dictionary
+#[cfg(feature = "pdf")]
+fn set_metadata(pdf: Vec<u8>, metadata: &PdfMetadata) -> Result<Vec<u8>, TexTmplError> {
+    let mut document = lopdf::Document::load_mem(&pdf).map_err(TexTmplError::Pdf)?;
+
+    let info_id = match document
+        .trailer
+        .get(b"Info")
+        .and_then(lopdf::Object::as_reference)
+    {
+        Ok(id) => id,
+        Err(_) => {
+            let id = document.add_object(lopdf::Dictionary::new());
+            document.trailer.set("Info", id);
+            id
+        }
+    };
+    let info = document
+        .get_object_mut(info_id)
+        .and_then(lopdf::Object::as_dict_mut)
+        .map_err(TexTmplError::Pdf)?;
+
+    let keywords = (!metadata.keywords.is_empty()).then(|| metadata.keywords.join(", "));
+    let entries = [
+        ("Title", metadata.title.as_ref()),
+        ("Author", metadata.author.as_ref()),
+        ("Subject", metadata.subject.as_ref()),
+        ("Keywords", keywords.as_ref()),
+    ];
+    for (key, value) in entries {
+        if let Some(value) = value {
+            info.set(key, lopdf::text_string(value));
+        }
+    }
+
+    let mut pdf = Vec::new();
+    document
+        .save_to(&mut pdf)
+        .map_err(TexTmplError::OutputStream)?;
+    Ok(pdf)
+}
+
+/// Writes `content` into a newly created file at `path`
+fn write_file(path: &Path, content: &[u8]) -> Result<(), TexTmplError> {
+    File::create(path)
+        .and_then(|mut file| file.write_all(content))
+        .map_err(|e| TexTmplError::OutputWrite(path.to_path_buf(), e))
+}
+
+/// Escapes LaTeX special characters (`& % $ # _ { } ~ ^ \`) in `s`
+pub fn latex_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '~' => escaped.push_str(r"\textasciitilde{}"),
+            '^' => escaped.push_str(r"\textasciicircum{}"),
+            '\\' => escaped.push_str(r"\textbackslash{}"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Commands whose arguments define a macro or an environment, in which `#` and `&` stand bare
+const DEFINITION_COMMANDS: [&str; 13] = [
+    "def",
+    "gdef",
+    "edef",
+    "xdef",
+    "newcommand",
+    "renewcommand",
+    "providecommand",
+    "DeclareRobustCommand",
+    "newenvironment",
+    "renewenvironment",
+    "NewDocumentCommand",
+    "RenewDocumentCommand",
+    "NewDocumentEnvironment",
+];
+
+/// Environments in which `&` separates columns, with or without a trailing `*`; any `...matrix`
+/// environment is one as well
+const ALIGNMENT_ENVIRONMENTS: [&str; 13] = [
+    "tabular",
+    "tabularx",
+    "tabulary",
+    "longtable",
+    "array",
+    "align",
+    "alignat",
+    "aligned",
+    "alignedat",
+    "flalign",
+    "split",
+    "cases",
+    "eqnarray",
+];
+
+/// Checks TeX for obvious mistakes which would otherwise surface as a hard to trace Tectonic
+/// error: unbalanced `{` and `}`, an unclosed `$` math shift, a `#` which is not a macro
+/// parameter and an `&` outside of an alignment environment such as `tabular` or `align`.
+/// Escaped characters (`\{`, `\$`, ...), comments and `\verb` or `verbatim` contents are skipped.
+/// Offsets in `TexTmplError::Validation` count characters from the start of `tex`.
+///
+/// `#` and `&` are accepted anywhere in the arguments of `\def`, `\newcommand` and the other
+/// `DEFINITION_COMMANDS`; alignments written with `\halign` or in custom environments are not
+/// recognized.
+pub fn validate_tex(tex: &str) -> Result<(), TexTmplError> {
+    let chars: Vec<char> = tex.chars().collect();
+    let mut open_braces = Vec::new();
+    let mut math_shift = None;
+    let mut environments: Vec<String> = Vec::new();
+    // the brace depth of the macro definition being read, and whether its arguments have begun
+    let mut definition: Option<(usize, bool)> = None;
+    let error = |offset, message: &str| Err(TexTmplError::Validation(offset, message.to_string()));
+
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+
+        // a definition ends at the first token at its own depth which is not one of its arguments
+        if let Some((depth, started)) = definition {
+            if open_braces.len() == depth {
+                match c {
+                    '[' => {
+                        while i < chars.len() && chars[i] != ']' {
+                            i += 1;
+                        }
+                        i += 1;
+                        continue;
+                    }
+                    '{' | '%' => {}
+                    '\\' | '*' | '#' if !started => {}
+                    _ if c.is_whitespace() => {}
+                    _ => definition = None,
+                }
+            }
+        }
+
+        match c {
+            '\\' => {
+                let name: String = chars[i + 1..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_alphabetic())
+                    .collect();
+                i += name.chars().count().max(1);
+
+                let argument = || {
+                    chars[i + 1..]
+                        .strip_prefix(&['{'])
+                        .and_then(|rest| {
+                            rest.iter().position(|&c| c == '}').map(|end| &rest[..end])
+                        })
+                        .map(|name| name.iter().collect::<String>())
+                };
+                match name.as_str() {
+                    "verb" => {
+                        // \verb|...| or \verb*|...|, delimited by any character
+                        let start = i + 1 + usize::from(chars.get(i + 1) == Some(&'*'));
+                        if let Some(&delimiter) = chars.get(start) {
+                            match chars[start + 1..].iter().position(|&c| c == delimiter) {
+                                Some(length) => i = start + 1 + length,
+                                None => return error(i, "unclosed \\verb"),
+                            }
+                        }
+                    }
+                    "begin" if argument().as_deref() == Some("verbatim") => {
+                        let rest: String = chars[i + 1..].iter().collect();
+                        match rest.find("\\end{verbatim}") {
+                            Some(end) => i += rest[..end].chars().count(),
+                            None => return error(i, "unclosed verbatim"),
+                        }
+                    }
+                    "begin" => environments.extend(argument()),
+                    "end" => {
+                        if let Some(position) = argument()
+                            .and_then(|name| environments.iter().rposition(|e| *e == name))
+                        {
+                            environments.truncate(position);
+                        }
+                    }
+                    _ if DEFINITION_COMMANDS.contains(&name.as_str()) => {
+                        definition = Some((open_braces.len(), false));
+                    }
+                    _ => {}
+                }
+            }
+            '%' => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            '{' => {
+                if let Some((depth, _)) = definition.filter(|&(d, _)| d == open_braces.len()) {
+                    definition = Some((depth, true));
+                }
+                open_braces.push(i);
+            }
+            '}' if open_braces.pop().is_none() => return error(i, "unmatched }"),
+            '$' => {
+                math_shift = match math_shift {
+                    Some(_) => None,
+                    None => Some(i),
+                }
+            }
+            '#' if definition.is_none() => return error(i, "unescaped #"),
+            // a parameter such as #1, or a doubled ## within a definition
+            '#' => i += 1,
+            '&' if definition.is_none()
+                && !environments.iter().any(|e| {
+                    let e = e.trim_end_matches('*');
+                    ALIGNMENT_ENVIRONMENTS.contains(&e) || e.ends_with("matrix")
+                }) =>
+            {
+                return error(i, "unescaped & outside of an alignment");
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    if let Some(&offset) = open_braces.first() {
+        return error(offset, "unclosed {");
+    }
+    if let Some(offset) = math_shift {
+        return error(offset, "unclosed $");
+    }
+    Ok(())
+}
+
+/// Helpers every registry has, those of Handlebars and `escape`, which a given helper must not
+/// replace
+const BUILT_IN_HELPERS: [&str; 18] = [
+    "if", "unless", "each", "with", "lookup", "raw", "log", "eq", "ne", "gt", "gte", "lt", "lte",
+    "and", "or", "not", "len", "escape",
+];
+
+/// Creates a registry with the built-in and the given helpers registered; fails with
+/// `TexTmplError::DuplicateHelper` when two of the given helpers share a name or one is named
+/// like a built-in helper
+fn registry<'r>(
+    helpers: Option<&[HandlebarsHelper]>,
+    escape: EscapeMode,
+) -> Result<Handlebars<'r>, TexTmplError> {
+    let mut hb_reg = Handlebars::new();
+
+    match escape {
+        EscapeMode::None => hb_reg.register_escape_fn(handlebars::no_escape),
+        EscapeMode::Latex => hb_reg.register_escape_fn(latex_escape),
+        EscapeMode::Custom(escape_fn) => hb_reg.register_escape_fn(escape_fn),
+    }
+    hb_reg.register_helper("escape", Box::new(helpers::escape));
+
+    if let Some(helpers) = helpers {
+        let mut names = BTreeSet::from(BUILT_IN_HELPERS);
+        for h in helpers {
+            if !names.insert(h.name.as_str()) {
+                return Err(TexTmplError::DuplicateHelper(h.name.clone()));
+            }
+            hb_reg.register_helper(&h.name, Box::new(SharedHelper(h.def.clone())));
+        }
+    }
+
+    Ok(hb_reg)
+}
+
+/// Returns `content` with Windows (CRLF) line endings turned into LF, so the TeX is the same
+/// whichever line endings the template was saved with
+fn normalize_line_endings(content: String) -> String {
+    if content.contains("\r\n") {
+        content.replace("\r\n", "\n")
+    } else {
+        content
+    }
+}
+
+/// Reads the template file at `path`
+fn read_template(path: &Path) -> Result<String, TexTmplError> {
+    read_to_string(path)
+        .map(normalize_line_endings)
+        .map_err(|e| TexTmplError::TemplateRead(path.to_path_buf(), e))
+}
+
+/// Reads the template file at `path` and decodes it from `encoding`, or from UTF-8 when `None`
+fn read_template_encoded(
+    path: &Path,
+    encoding: Option<&'static Encoding>,
+) -> Result<String, TexTmplError> {
+    let Some(encoding) = encoding.filter(|e| *e != encoding_rs::UTF_8) else {
+        return read_template(path);
+    };
+
+    let bytes =
+        std::fs::read(path).map_err(|e| TexTmplError::TemplateRead(path.to_path_buf(), e))?;
+    encoding
+        .decode_without_bom_handling_and_without_replacement(&bytes)
+        .map(|content| normalize_line_endings(content.into_owned()))
+        .ok_or_else(|| {
+            TexTmplError::TemplateRead(
+                path.to_path_buf(),
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("stream did not contain valid {}", encoding.name()),
+                ),
+            )
+        })
+}
+
+/// Returns the offsets in `tex` right after every `\name` command; as in `validate_tex`, escaped
+/// characters and comments are skipped, and so are longer commands such as `\namex`
+fn command_ends(tex: &str, name: &str) -> Vec<usize> {
+    let mut ends = Vec::new();
+    let mut chars = tex.char_indices();
+
+    while let Some((offset, c)) = chars.next() {
+        match c {
+            '\\' => {
+                let rest = &tex[offset + 1..];
+                if rest.starts_with(name)
+                    && !rest[name.len()..].starts_with(|c: char| c.is_ascii_alphabetic())
+                {
+                    ends.push(offset + 1 + name.len());
+                }
+                chars.next();
+            }
+            '%' => {
+                for (_, c) in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    ends
+}
+
+/// Returns the start and end of the contents of the `{...}` argument of the command which ends at
+/// `start` in `tex`, skipping an optional `[...]` argument before it; braces nested in the
+/// argument are skipped over
+fn command_argument(tex: &str, start: usize) -> Option<(usize, usize)> {
+    let mut rest = tex[start..].trim_start();
+    if rest.starts_with('[') {
+        rest = rest[rest.find(']')? + 1..].trim_start();
+    }
+    let open = tex.len() - rest.len();
+    if !rest.starts_with('{') {
+        return None;
+    }
+
+    let mut depth = 0;
+    let mut chars = rest.char_indices();
+    while let Some((offset, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some((open + 1, open + offset));
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Returns `tex` with `\usepackage{...}` lines for those of `packages` it does not load yet inserted
+/// after its `\documentclass`
+fn add_packages(tex: &str, packages: &[String]) -> Result<String, TexTmplError> {
+    let mut loaded = BTreeSet::new();
+    for command in ["usepackage", "RequirePackage"] {
+        for end in command_ends(tex, command) {
+            if let Some((open, close)) = command_argument(tex, end) {
+                // a list spread over several lines may have comments after its commas
+                let names: String = tex[open..close]
+                    .lines()
+                    .map(|line| line.split('%').next().unwrap_or_default())
+                    .collect();
+                loaded.extend(names.split(',').map(|name| name.trim().to_string()));
+            }
+        }
+    }
+
+    let Some((_, class_end)) = command_ends(tex, "documentclass")
+        .first()
+        .and_then(|&end| command_argument(tex, end))
+    else {
+        return Err(TexTmplError::Validation(
+            0,
+            "no \\documentclass to load the packages after".to_string(),
+        ));
+    };
+
+    let mut preamble = String::new();
+    for package in packages {
+        if loaded.insert(package.trim().to_string()) {
+            preamble.push_str(&format!("\n\\usepackage{{{}}}", package.trim()));
+        }
+    }
+
+    let mut spliced = String::with_capacity(tex.len() + preamble.len());
+    spliced.push_str(&tex[..=class_end]);
+    spliced.push_str(&preamble);
+    spliced.push_str(&tex[class_end + 1..]);
+    Ok(spliced)
+}
+
+/// Returns whether `template` contains any Handlebars expression, helper or partial; comments
+/// and escaped `\{{` do not count
+fn has_placeholders(template: &str) -> Result<bool, TexTmplError> {
+    let template = Template::compile(template)?;
+
+    Ok(template.elements.iter().any(|element| {
+        !matches!(
+            element,
+            TemplateElement::RawString(_) | TemplateElement::Comment(_)
+        )
+    }))
+}
+
+/// Rewrites a template written with custom `open`/`close` delimiters into the standard `{{`/`}}`
+/// syntax, escaping any literal `{{` so that it reaches the output unchanged
+fn translate_delimiters(template: &str, open: &str, close: &str) -> String {
+    let mut translated = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find(open) {
+        let after_open = &rest[start + open.len()..];
+        let Some(end) = after_open.find(close) else {
+            break;
+        };
+        let text = rest[..start].replace("{{", "\\{{");
+        let tag = &after_open[..end];
+
+        translated.push_str(&text);
+        // a `{` or `\` right before `{{` would change its meaning, so keep them apart with a space
+        // which the `~` whitespace control strips again
+        if text.ends_with(['{', '\\']) {
+            translated.push(' ');
+            translated.push_str(if tag.starts_with('~') { "{{" } else { "{{~" });
+        } else {
+            translated.push_str("{{");
+        }
+        translated.push_str(tag);
+        translated.push_str("}}");
+
+        rest = &after_open[end + close.len()..];
+    }
+    translated.push_str(&rest.replace("{{", "\\{{"));
+
+    translated
+}
+
+/// A template parsed once into a Handlebars registry together with its helpers, which can then be
+/// rendered against any number of data values
+pub struct CompiledTemplate {
+    registry: Handlebars<'static>,
+    name: String,
+}
+
+impl CompiledTemplate {
+    const NAME: &'static str = "tex_template";
+    const LAYOUT_NAME: &'static str = "tex_layout";
+
+    /// Parses a template string and registers an optional slice of `HandlebarsHelper`
+    pub fn new(
+        template: &str,
+        helpers: Option<&[HandlebarsHelper]>,
+    ) -> Result<CompiledTemplate, TexTmplError> {
+        Self::with_registry(registry(helpers, EscapeMode::None)?, Self::NAME, template)
+    }
+
+    /// Parses the template of `TemplateRecipe` with its helpers and escape mode; the data of the
+    /// recipe is not used
+    pub fn from_recipe<T: serde::Serialize>(
+        recipe: &TemplateRecipe<T>,
+    ) -> Result<CompiledTemplate, TexTmplError> {
+        let mut registry = registry(recipe.helpers.as_deref(), recipe.escape)?;
+        registry.set_strict_mode(recipe.strict);
+        if let Some(configure) = &recipe.configure_registry {
+            configure(&mut registry);
+        }
+        let read = |path: &Path| {
+            let content = read_template_encoded(path, recipe.encoding)?;
+            Ok::<_, TexTmplError>(match &recipe.delimiters {
+                Some((open, close)) => translate_delimiters(&content, open, close),
+                None => content,
+            })
+        };
+
+        if let Some(partials) = &recipe.partials {
+            for (name, path) in partials {
+                read(path)
+                    .and_then(|content| Ok(registry.register_partial(name, content)?))
+                    .map_err(|e| TexTmplError::Partial(name.clone(), Box::new(e)))?;
+            }
+        }
+
+        let mut tex_content = read(recipe.template)?;
+        if recipe.require_placeholders && !has_placeholders(&tex_content)? {
+            return Err(TexTmplError::Validation(
+                0,
+                format!(
+                    "template {} contains no Handlebars expressions",
+                    recipe.template.display()
+                ),
+            ));
+        }
+
+        if let Some(layout) = recipe.layout {
+            registry.register_partial(Self::LAYOUT_NAME, read(layout)?)?;
+            tex_content = format!(
+                "{{{{#> {name}}}}}{}{{{{/{name}}}}}",
+                tex_content,
+                name = Self::LAYOUT_NAME
+            );
+        }
+
+        let name = recipe.template_name.as_deref().unwrap_or(Self::NAME);
+        Self::with_registry(registry, name, &tex_content)
+    }
+
+    fn with_registry(
+        mut registry: Handlebars<'static>,
+        name: &str,
+        template: &str,
+    ) -> Result<CompiledTemplate, TexTmplError> {
+        registry.register_template_string(name, template)?;
+
+        Ok(CompiledTemplate {
+            registry,
+            name: name.to_string(),
+        })
+    }
+
+    /// Returns the name the template is registered under
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Outputs TeX rendered with `data`
+    pub fn render<T: serde::Serialize>(&self, data: &T) -> Result<String, TexTmplError> {
+        Ok(self.registry.render(&self.name, data)?)
+    }
+
+    /// Outputs TeX rendered with each of `rows`, e.g. to compile them elsewhere; the template is
+    /// parsed only once and the results line up index-for-index with `rows`
+    pub fn render_all<T: serde::Serialize>(&self, rows: &[T]) -> Vec<Result<String, TexTmplError>> {
+        rows.iter().map(|data| self.render(data)).collect()
+    }
+}
+
+/// A context entered by a block helper while collecting variables
+struct VariableScope {
+    /// Path of the context, e.g. `items` inside `{{#each items}}`
+    prefix: String,
+    /// Block parameters (`as |item|`) and the paths they stand for
+    params: Vec<(String, String)>,
+}
+
+/// Resolves a path as written in a template against `scopes` into a path from the data root;
+/// `None` for locals such as `@index` and for the context itself
+fn resolve_variable(raw: &str, scopes: &[VariableScope]) -> Option<String> {
+    let mut path = raw.replace('/', ".");
+    let mut depth = scopes.len() - 1;
+
+    if let Some(rest) = path.strip_prefix("@root.") {
+        path = rest.to_string();
+        depth = 0;
+    }
+    while let Some(rest) = path.strip_prefix("..") {
+        path = rest.trim_start_matches('.').to_string();
+        depth = depth.saturating_sub(1);
+    }
+    if path.starts_with('@') {
+        return None;
+    }
+    let path = match path.as_str() {
+        "this" | "." | "" => return None,
+        _ => path
+            .strip_prefix("this.")
+            .or_else(|| path.strip_prefix("./"))
+            .unwrap_or(&path)
+            .to_string(),
+    };
+
+    let (head, tail) = match path.split_once('.') {
+        Some((head, tail)) => (head, Some(tail)),
+        None => (path.as_str(), None),
+    };
+    for scope in scopes[..=depth].iter().rev() {
+        if let Some((_, prefix)) = scope.params.iter().find(|(name, _)| name == head) {
+            return tail.map(|tail| format!("{}.{}", prefix, tail));
+        }
+    }
+
+    match scopes[depth].prefix.as_str() {
+        "" => Some(path),
+        prefix => Some(format!("{}.{}", prefix, path)),
+    }
+}
+
+/// Collects the variables of a helper parameter and returns the path it resolves to
+fn collect_parameter(
+    param: &Parameter,
+    scopes: &mut Vec<VariableScope>,
+    variables: &mut BTreeSet<String>,
+) -> Option<String> {
+    match param {
+        Parameter::Path(_) => {
+            let variable = resolve_variable(param.as_name()?, scopes)?;
+            variables.insert(variable.clone());
+            Some(variable)
+        }
+        Parameter::Subexpression(subexpression) => {
+            collect_element(&subexpression.element, scopes, variables);
+            None
+        }
+        Parameter::Name(_) | Parameter::Literal(_) => None,
+    }
+}
+
+/// Collects the variables of an expression or a block helper and of its inner templates
+fn collect_helper(
+    helper: &HelperTemplate,
+    scopes: &mut Vec<VariableScope>,
+    variables: &mut BTreeSet<String>,
+) {
+    if !helper.block && helper.params.is_empty() && helper.hash.is_empty() {
+        if let Some(variable) = helper
+            .name
+            .as_name()
+            .and_then(|name| resolve_variable(name, scopes))
+        {
+            variables.insert(variable);
+        }
+        return;
+    }
+
+    let mut context = None;
+    for (i, param) in helper.params.iter().enumerate() {
+        let variable = collect_parameter(param, scopes, variables);
+        if i == 0 {
+            context = variable;
+        }
+    }
+    for param in helper.hash.values() {
+        collect_parameter(param, scopes, variables);
+    }
+
+    if let Some(template) = &helper.template {
+        match (helper.name.as_name(), context) {
+            (Some("each") | Some("with"), Some(prefix)) => {
+                let params = match &helper.block_param {
+                    Some(BlockParam::Single(name)) | Some(BlockParam::Pair((name, _))) => name
+                        .as_name()
+                        .map(|name| vec![(name.to_string(), prefix.clone())])
+                        .unwrap_or_default(),
+                    None => Vec::new(),
+                };
+                scopes.push(VariableScope { prefix, params });
+                collect_template(template, scopes, variables);
+                scopes.pop();
+            }
+            _ => collect_template(template, scopes, variables),
+        }
+    }
+    if let Some(inverse) = &helper.inverse {
+        collect_template(inverse, scopes, variables);
+    }
+}
+
+/// Collects the variables of a template element
+fn collect_element(
+    element: &TemplateElement,
+    scopes: &mut Vec<VariableScope>,
+    variables: &mut BTreeSet<String>,
+) {
+    match element {
+        TemplateElement::Expression(helper)
+        | TemplateElement::HtmlExpression(helper)
+        | TemplateElement::HelperBlock(helper) => collect_helper(helper, scopes, variables),
+        TemplateElement::PartialExpression(decorator)
+        | TemplateElement::PartialBlock(decorator)
+        | TemplateElement::DecoratorExpression(decorator)
+        | TemplateElement::DecoratorBlock(decorator) => {
+            for param in decorator.params.iter().chain(decorator.hash.values()) {
+                collect_parameter(param, scopes, variables);
+            }
+            if let Some(template) = &decorator.template {
+                collect_template(template, scopes, variables);
+            }
+        }
+        TemplateElement::RawString(_) | TemplateElement::Comment(_) => {}
+    }
+}
+
+/// Collects the variables of every element of a template
+fn collect_template(
+    template: &Template,
+    scopes: &mut Vec<VariableScope>,
+    variables: &mut BTreeSet<String>,
+) {
+    for element in &template.elements {
+        collect_element(element, scopes, variables);
+    }
+}
+
+/// Returns the sorted paths of the values a template references, e.g. `customer.name` for
+/// `{{customer.name}}`; paths inside `{{#each items}}` and `{{#with item}}` blocks are prefixed
+/// with the block's context (`items.price`), locals such as `@index` are left out. Expressions
+/// with arguments are treated as helper calls and only their arguments are reported, while
+/// `{{name}}` is reported even when it is a helper without arguments.
+pub fn template_variables(template: &str) -> Result<Vec<String>, TexTmplError> {
+    let template = Template::compile(template)?;
+
+    let mut variables = BTreeSet::new();
+    let mut scopes = vec![VariableScope {
+        prefix: String::new(),
+        params: Vec::new(),
+    }];
+    collect_template(&template, &mut scopes, &mut variables);
+
+    Ok(variables.into_iter().collect())
+}
+
+/// Outputs TeX from a template string, data and an optional slice of `HandlebarsHelper`
+pub fn prepare_tex_from_str<T: serde::Serialize>(
+    template: &str,
+    data: &T,
+    helpers: Option<&[HandlebarsHelper]>,
+) -> Result<String, TexTmplError> {
+    CompiledTemplate::new(template, helpers)?.render(data)
+}
+
+/// Outputs TeX from a template read as UTF-8, with CRLF line endings turned into LF, from
+/// `reader`, e.g. a `&[u8]` from `include_bytes!` or a network stream, data and an optional slice
+/// of `HandlebarsHelper`
+pub fn prepare_tex_from_reader<R: Read, T: serde::Serialize>(
+    mut reader: R,
+    data: &T,
+    helpers: Option<&[HandlebarsHelper]>,
+) -> Result<String, TexTmplError> {
+    let mut template = String::new();
+    reader
+        .read_to_string(&mut template)
+        .map_err(TexTmplError::TemplateStream)?;
+    prepare_tex_from_str(&normalize_line_endings(template), data, helpers)
+}
+
+/// Renders `template` with `data` into TeX with `packages` and those required by the helpers
+/// (see `helpers::require_package`) loaded after `\documentclass`
+fn render_with_packages<T: serde::Serialize>(
+    template: &CompiledTemplate,
+    data: &T,
+    packages: &[String],
+) -> Result<String, TexTmplError> {
+    helpers::take_required_packages();
+    let tex = template.render(data)?;
+
+    let mut packages = packages.to_vec();
+    // a template without \documentclass, e.g. a part `\input` by another document, leaves the
+    // packages its helpers need to that document
+    if !command_ends(&tex, "documentclass").is_empty() {
+        packages.extend(helpers::take_required_packages());
+    }
+    if packages.is_empty() {
+        return Ok(tex);
+    }
+    add_packages(&tex, &packages)
+}
+
+/// Outputs TeX to compile from a template string like `prepare_tex_from_str`, with the packages
+/// required by the helpers loaded as by `prepare_tex`
+#[cfg(feature = "pdf")]
+fn prepare_tex_to_compile<T: serde::Serialize>(
+    template: &str,
+    data: &T,
+    helpers: Option<&[HandlebarsHelper]>,
+) -> Result<String, TexTmplError> {
+    render_with_packages(&CompiledTemplate::new(template, helpers)?, data, &[])
+}
+
+/// Outputs TeX from `TemplateRecipe`, with `recipe.packages` and those required by the helpers
+/// (see `helpers::require_package`) loaded after `\documentclass`
+pub fn prepare_tex<T: serde::Serialize>(
+    recipe: &TemplateRecipe<T>,
+) -> Result<String, TexTmplError> {
+    let template = CompiledTemplate::from_recipe(recipe)?;
+    let tex = render_with_packages(&template, recipe.data, &recipe.packages)?;
+
+    if recipe.validate {
+        validate_tex(&tex)?;
+    }
+    Ok(tex)
+}
+
+/// Outputs TeX for each of the `(name, path)` templates, rendered with the same data and helpers
+/// into a map from name to TeX
+///
+/// All the templates are registered into one registry, so each is also usable in the others as
+/// the partial `{{> name}}`.
+pub fn prepare_tex_all<T: serde::Serialize>(
+    templates: &[(String, PathBuf)],
+    data: &T,
+    helpers: Option<&[HandlebarsHelper]>,
+) -> Result<HashMap<String, String>, TexTmplError> {
+    let mut registry = registry(helpers, EscapeMode::None)?;
+    for (name, path) in templates {
+        registry.register_template_string(name, read_template(path)?)?;
+    }
+
+    templates
+        .iter()
+        .map(|(name, _)| Ok((name.clone(), registry.render(name, data)?)))
+        .collect()
+}
+
+/// The `{{#each_row}}` block helper of `write_tex_streaming`, which renders its block for every
+/// row taken from the iterator
+struct RowStream<I: Iterator> {
+    rows: Mutex<std::iter::Peekable<I>>,
+}
+
+impl<I> HelperDef for RowStream<I>
+where
+    I: Iterator + Send,
+    I::Item: serde::Serialize + Send,
+{
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        r: &'reg Handlebars<'reg>,
+        ctx: &'rc Context,
+        rc: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let template = h
+            .template()
+            .ok_or_else(|| RenderError::new("each_row has to be used as a block"))?;
+        let mut rows = self
+            .rows
+            .lock()
+            .map_err(|_| RenderError::new("each_row rows are poisoned"))?;
+
+        let mut index = 0;
+        while let Some(row) = rows.next() {
+            let value =
+                serde_json::to_value(row).map_err(|e| RenderError::from_error("each_row", e))?;
+
+            let mut block = BlockContext::new();
+            block.set_base_value(value);
+            block.set_local_var("index", index.into());
+            block.set_local_var("first", (index == 0).into());
+            block.set_local_var("last", rows.peek().is_none().into());
+            rc.push_block(block);
+            let rendered = template.render(r, ctx, rc, out);
+            rc.pop_block();
+            rendered?;
+
+            index += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Writes TeX rendered from a template string into `writer`, taking the rows of its
+/// `{{#each_row}}...{{/each_row}}` block one at a time from `rows`
+///
+/// Only the current row and `data`, the context outside of the block, are held in memory, and the
+/// TeX is written as it is rendered, so data sets too large to collect into one value can be
+/// rendered. Inside the block the row is the context, `../` reaches `data`, and `@index`,
+/// `@first` and `@last` are set as in `{{#each}}`. The rows can be iterated only once, so the
+/// block should appear once. Tectonic needs the whole TeX in memory, so a document rendered this
+/// way is compiled from the written file.
+pub fn write_tex_streaming<T, I, W>(
+    template: &str,
+    data: &T,
+    rows: I,
+    helpers: Option<&[HandlebarsHelper]>,
+    writer: W,
+) -> Result<(), TexTmplError>
+where
+    T: serde::Serialize,
+    I: IntoIterator,
+    I::IntoIter: Send,
+    I::Item: serde::Serialize + Send,
+    W: Write,
+{
+    if let Some(h) = helpers.into_iter().flatten().find(|h| h.name == "each_row") {
+        return Err(TexTmplError::DuplicateHelper(h.name.clone()));
+    }
+    let mut registry = registry(helpers, EscapeMode::None)?;
+    registry.register_helper(
+        "each_row",
+        Box::new(RowStream {
+            rows: Mutex::new(rows.into_iter().peekable()),
+        }),
+    );
+
+    Ok(registry.render_template_to_write(template, data, writer)?)
+}
+
+/// Outputs PDF bytes from a template path, data and an optional slice of `HandlebarsHelper` using
+/// Tectonic
+#[cfg(feature = "pdf")]
+pub fn render_pdf_to_bytes<T: serde::Serialize>(
+    template: &Path,
+    data: &T,
+    helpers: Option<&[HandlebarsHelper]>,
+) -> Result<Vec<u8>, TexTmplError> {
+    let tex_content = read_template(template)?;
+    let tex = prepare_tex_to_compile(&tex_content, data, helpers)?;
+
+    compile_pdf(
+        &tex,
+        &TectonicOptions {
+            working_dir: template.parent().map(Path::to_path_buf),
+            ..TectonicOptions::default()
+        },
+        &mut NoopStatusBackend::default(),
+    )
+}
+
+/// Outputs PDF bytes from a template string, data and an optional slice of `HandlebarsHelper`
+/// entirely in memory, e.g. for integration tests of downstream crates exercising the whole path
+/// without temporary files; relative paths in the template resolve against the current directory
+///
+/// Output which is not a PDF, i.e. does not start with `%PDF`, fails with `TexTmplError::Compile`.
+#[cfg(feature = "pdf")]
+pub fn render_str_to_pdf<T: serde::Serialize>(
+    template: &str,
+    data: &T,
+    helpers: Option<&[HandlebarsHelper]>,
+) -> Result<Vec<u8>, TexTmplError> {
+    let tex = prepare_tex_to_compile(template, data, helpers)?;
+    let pdf_data = compile_pdf(
+        &tex,
+        &TectonicOptions::default(),
+        &mut NoopStatusBackend::default(),
+    )?;
+
+    if !pdf_data.starts_with(b"%PDF") {
+        return Err(TexTmplError::compile(
+            "Tectonic did not produce a valid PDF",
+        ));
+    }
+    Ok(pdf_data)
+}
+
+/// Renders `TemplateRecipe` into PDF bytes
+#[cfg(feature = "pdf")]
+fn recipe_to_pdf<T: serde::Serialize>(recipe: &TemplateRecipe<T>) -> Result<Vec<u8>, TexTmplError> {
+    let tex = prepare_tex(recipe)?;
+
+    compile_recipe(&tex, recipe, &mut NoopStatusBackend::default())
+}
+
+/// Outputs PDF bytes like `render_pdf_to_bytes`, but runs the Tectonic compilation on the Tokio
+/// blocking thread pool so that the async runtime is not blocked
+#[cfg(feature = "tokio")]
+pub async fn render_pdf_async<T: serde::Serialize>(
+    template: &Path,
+    data: &T,
+    helpers: Option<&[HandlebarsHelper]>,
+) -> Result<Vec<u8>, TexTmplError> {
+    let tex_content = read_template(template)?;
+    let tex = prepare_tex_to_compile(&tex_content, data, helpers)?;
+
+    let options = TectonicOptions {
+        working_dir: template.parent().map(Path::to_path_buf),
+        ..TectonicOptions::default()
+    };
+
+    tokio::task::spawn_blocking(move || {
+        compile_pdf(&tex, &options, &mut NoopStatusBackend::default())
+    })
+    .await
+    .map_err(|e| TexTmplError::compile(format!("compilation task failed: {}", e)))?
+}
+
+/// Outputs PDF from `TemplateRecipe` using Tectonic
+#[cfg(feature = "pdf")]
+pub fn render_pdf<T: serde::Serialize>(recipe: &TemplateRecipe<T>) -> Result<(), TexTmplError> {
+    if recipe.keep_output()? {
+        return Ok(());
+    }
+    let pdf_data = recipe_to_pdf(recipe)?;
+
+    recipe.write_output(&pdf_data)
+}
+
+/// Outputs TeX into `tex_path` and PDF into `recipe.output` from `TemplateRecipe`, writing neither
+/// unless the compilation succeeds
+///
+/// Both files are written under temporary names next to their targets and then renamed over them,
+/// so readers see either the previous files or the complete new pair, never a partial file or a
+/// PDF which does not belong to the TeX.
+#[cfg(feature = "pdf")]
+pub fn render_both<T: serde::Serialize>(
+    recipe: &TemplateRecipe<T>,
+    tex_path: &Path,
+) -> Result<(), TexTmplError> {
+    if recipe.keep_output()? {
+        return Ok(());
+    }
+    let tex = prepare_tex(recipe)?;
+    let pdf_data = compile_recipe(&tex, recipe, &mut NoopStatusBackend::default())?;
+    let output = recipe.output_dir()?;
+
+    let partial_tex = partial_path(tex_path);
+    let partial_pdf = partial_path(output);
+    let written = write_file(&partial_tex, tex.as_bytes())
+        .and_then(|_| write_file(&partial_pdf, &pdf_data))
+        .and_then(|_| {
+            std::fs::rename(&partial_tex, tex_path)
+                .map_err(|e| TexTmplError::OutputWrite(tex_path.to_path_buf(), e))
+        })
+        .and_then(|_| {
+            std::fs::rename(&partial_pdf, output)
+                .map_err(|e| TexTmplError::OutputWrite(output.to_path_buf(), e))
+        });
+
+    if written.is_err() {
+        let _ = std::fs::remove_file(&partial_tex);
+        let _ = std::fs::remove_file(&partial_pdf);
+    }
+    written
+}
+
+/// Returns the temporary name `path` is written under before it is renamed into place, e.g.
+/// `report.pdf.1234.tmp` for process 1234
+#[cfg(feature = "pdf")]
+fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}.tmp", std::process::id()));
+    PathBuf::from(name)
+}
+
+/// Reads `path` and parses it as JSON
+#[cfg(feature = "pdf")]
+fn read_json(path: &Path) -> Result<serde_json::Value, TexTmplError> {
+    let content =
+        read_to_string(path).map_err(|e| TexTmplError::Data(path.to_path_buf(), Box::new(e)))?;
+
+    serde_json::from_str(&content).map_err(|e| TexTmplError::Data(path.to_path_buf(), Box::new(e)))
+}
+
+/// Outputs PDF from a template path and the data in the JSON file at `json_path` like
+/// `render_pdf`; a JSON file which cannot be read or parsed fails with `TexTmplError::Data`
+#[cfg(feature = "pdf")]
+pub fn render_pdf_from_json(
+    template: &Path,
+    json_path: &Path,
+    output: &Path,
+) -> Result<(), TexTmplError> {
+    let data = read_json(json_path)?;
+
+    let recipe = TemplateRecipe::builder()
+        .template(template)
+        .output(output)
+        .data(&data)
+        .build()?;
+
+    render_pdf(&recipe)
+}
+
+/// Reads `path` and parses it as YAML
+#[cfg(feature = "yaml")]
+fn read_yaml(path: &Path) -> Result<serde_yaml::Value, TexTmplError> {
+    let content =
+        read_to_string(path).map_err(|e| TexTmplError::Data(path.to_path_buf(), Box::new(e)))?;
+
+    serde_yaml::from_str(&content).map_err(|e| TexTmplError::Data(path.to_path_buf(), Box::new(e)))
+}
+
+/// Outputs TeX from a template path and the data in the YAML file at `yaml_path`; a YAML file
+/// which cannot be read or parsed fails with `TexTmplError::Data`
+#[cfg(feature = "yaml")]
+pub fn prepare_tex_from_yaml(template: &Path, yaml_path: &Path) -> Result<String, TexTmplError> {
+    let data = read_yaml(yaml_path)?;
+    let tex_content = read_template(template)?;
+
+    prepare_tex_from_str(&tex_content, &data, None)
+}
+
+/// Outputs PDF from a template path and the data in the YAML file at `yaml_path` like
+/// `render_pdf_from_json`
+#[cfg(all(feature = "yaml", feature = "pdf"))]
+pub fn render_pdf_from_yaml(
+    template: &Path,
+    yaml_path: &Path,
+    output: &Path,
+) -> Result<(), TexTmplError> {
+    let data = read_yaml(yaml_path)?;
+
+    let recipe = TemplateRecipe::builder()
+        .template(template)
+        .output(output)
+        .data(&data)
+        .build()?;
+
+    render_pdf(&recipe)
+}
+
+/// Reads the CSV file at `path` into `{"rows": [{column: value, ...}, ...]}`, keyed by the header
+/// row, for use as recipe data with `{{#each rows}}`; every value is a string and a file which
+/// cannot be read or parsed fails with `TexTmplError::Data`
+#[cfg(feature = "csv")]
+pub fn load_csv(path: &Path) -> Result<serde_json::Value, TexTmplError> {
+    let data_error = |e: csv::Error| TexTmplError::Data(path.to_path_buf(), Box::new(e));
+
+    let mut reader = csv::Reader::from_path(path).map_err(data_error)?;
+    let headers = reader.headers().map_err(data_error)?.clone();
+
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(data_error)?;
+        let row: serde_json::Map<String, serde_json::Value> = headers
+            .iter()
+            .zip(record.iter())
+            .map(|(header, value)| (header.to_string(), value.into()))
+            .collect();
+        rows.push(serde_json::Value::Object(row));
+    }
+
+    Ok(serde_json::json!({ "rows": rows }))
+}
+
+/// Size and SHA-256 hash of rendered TeX, which change only with the content of the document and
+/// not with the run-to-run differences of the PDF Tectonic compiles from it
+#[cfg(feature = "digest")]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TexDigest {
+    /// Length of the TeX in bytes
+    pub bytes: usize,
+    /// SHA-256 hash of the TeX as 64 lowercase hexadecimal digits
+    pub sha256: String,
+}
+
+#[cfg(feature = "digest")]
+impl TexDigest {
+    /// Returns the digest of `tex`
+    pub fn of(tex: &str) -> TexDigest {
+        TexDigest {
+            bytes: tex.len(),
+            sha256: sha256_hex(&[tex.as_bytes()]),
+        }
+    }
+}
+
+/// Returns the SHA-256 hash of the concatenated `parts` in lowercase hexadecimal
+#[cfg(feature = "digest")]
+fn sha256_hex(parts: &[&[u8]]) -> String {
+    use sha2::{Digest, Sha256};
+
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Outputs TeX from `TemplateRecipe` like `prepare_tex` together with its `TexDigest`, e.g. to
+/// notice whether a document changed without compiling it
+#[cfg(feature = "digest")]
+pub fn prepare_tex_digest<T: serde::Serialize>(
+    recipe: &TemplateRecipe<T>,
+) -> Result<(String, TexDigest), TexTmplError> {
+    let tex = prepare_tex(recipe)?;
+    let digest = TexDigest::of(&tex);
+    Ok((tex, digest))
+}
+
+/// A directory of PDFs rendered by `render_pdf_cached`, keyed by a SHA-256 hash of the rendered
+/// TeX and the options it is compiled with
+#[cfg(feature = "cache")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PdfCache {
+    dir: PathBuf,
+}
+
+#[cfg(feature = "cache")]
+impl PdfCache {
+    /// Returns a cache kept in `dir`, which is created on the first store
+    pub fn new<P: Into<PathBuf>>(dir: P) -> PdfCache {
+        PdfCache { dir: dir.into() }
+    }
+
+    /// Returns the directory of the cache
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Removes every cached PDF; other files in the directory are left alone
+    pub fn clear(&self) -> Result<(), TexTmplError> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(TexTmplError::OutputWrite(self.dir.clone(), e)),
+        };
+        for entry in entries {
+            let path = entry
+                .map_err(|e| TexTmplError::OutputWrite(self.dir.clone(), e))?
+                .path();
+            if path.extension().is_some_and(|extension| extension == "pdf") {
+                std::fs::remove_file(&path).map_err(|e| TexTmplError::OutputWrite(path, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the path under which the PDF of `tex` compiled for `recipe` is cached
+    fn path<T: serde::Serialize>(&self, tex: &str, recipe: &TemplateRecipe<T>) -> PathBuf {
+        let options = format!("{:?}{:?}", recipe.tectonic_options(), recipe.metadata);
+        let key = sha256_hex(&[tex.as_bytes(), options.as_bytes()]);
+
+        self.dir.join(format!("{}.pdf", key))
+    }
+}
+
+/// Outputs PDF from `TemplateRecipe` like `render_pdf`, but takes it from `cache` when the same
+/// TeX was compiled with the same options before, and stores it there otherwise
+///
+/// The key covers the rendered TeX, so changes to the template, its data and helpers are noticed,
+/// but files the document reads while compiling, such as `\input` files and images, are not;
+/// `PdfCache::clear` the cache when those change.
+#[cfg(feature = "cache")]
+pub fn render_pdf_cached<T: serde::Serialize>(
+    recipe: &TemplateRecipe<T>,
+    cache: &PdfCache,
+) -> Result<(), TexTmplError> {
+    if recipe.keep_output()? {
+        return Ok(());
+    }
+    let tex = prepare_tex(recipe)?;
+    let cached_path = cache.path(&tex, recipe);
+
+    let pdf_data = match std::fs::read(&cached_path) {
+        Ok(pdf_data) => pdf_data,
+        Err(_) => {
+            let pdf_data = compile_recipe(&tex, recipe, &mut NoopStatusBackend::default())?;
+
+            // written under a temporary name first so that no one reads a partial PDF
+            std::fs::create_dir_all(&cache.dir)
+                .map_err(|e| TexTmplError::OutputWrite(cache.dir.clone(), e))?;
+            let partial_path = cached_path.with_extension(format!("{}.tmp", std::process::id()));
+            write_file(&partial_path, &pdf_data)?;
+            std::fs::rename(&partial_path, &cached_path)
+                .map_err(|e| TexTmplError::OutputWrite(cached_path.clone(), e))?;
+
+            pdf_data
+        }
+    };
+
+    recipe.write_output(&pdf_data)
+}
+
+/// Renders and compiles `TemplateRecipe` into Tectonic's intermediate XDV (extended DVI) instead
+/// of PDF and returns its bytes, without writing `recipe.output`; `metadata` is not applied
+///
+/// XDV refers to the fonts and images it uses by name rather than embedding them, so whatever
+/// interprets it, e.g. `xdvipdfmx`, needs the same fonts and Tectonic bundle as the compilation.
+#[cfg(feature = "pdf")]
+pub fn render_xdv<T: serde::Serialize>(
+    recipe: &TemplateRecipe<T>,
+) -> Result<Vec<u8>, TexTmplError> {
+    let tex = prepare_tex(recipe)?;
+
+    TexEngine::new()?.compile(
+        &tex,
+        &recipe.tectonic_options(),
+        OutputFormat::Xdv,
+        &mut NoopStatusBackend::default(),
+    )
+}
+
+/// Renders and compiles `TemplateRecipe` like `render_pdf`, but discards the PDF instead of
+/// writing `recipe.output`; returns the error `render_pdf` would have failed with, if any
+#[cfg(feature = "pdf")]
+pub fn check<T: serde::Serialize>(recipe: &TemplateRecipe<T>) -> Result<(), TexTmplError> {
+    recipe_to_pdf(recipe).map(|_| ())
+}
+
+/// Which pages `render_png` and `render_svg` convert
+#[cfg(any(feature = "png", feature = "svg"))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PageSelection {
+    /// Only the first page, e.g. for a thumbnail
+    #[default]
+    First,
+    /// Every page
+    All,
+}
+
+/// The former name of `PageSelection`
+#[cfg(feature = "png")]
+pub type PngPages = PageSelection;
+
+/// Runs `f` with a newly created temporary directory in `tectonic.temp_dir`, or in the system's
+/// temporary directory, which is removed afterwards
+#[cfg(any(feature = "png", feature = "svg"))]
+fn with_temp_dir<R>(
+    tectonic: &TectonicOptions,
+    f: impl FnOnce(&Path) -> Result<R, TexTmplError>,
+) -> Result<R, TexTmplError> {
+    let parent = tectonic.temp_dir.clone().unwrap_or_else(std::env::temp_dir);
+    let dir = TempDir::new(&parent, false)?;
+
+    f(&dir.path)
+}
+
+/// Runs the Poppler tool of `command` and fails with `TexTmplError::Rasterize` when it cannot be
+/// started or does not succeed
+#[cfg(any(feature = "png", feature = "svg"))]
+fn run_converter(command: &mut std::process::Command) -> Result<(), TexTmplError> {
+    let tool = command.get_program().to_string_lossy().into_owned();
+    let output = command
+        .output()
+        .map_err(|e| TexTmplError::Rasterize(format!("cannot run {}: {}", tool, e)))?;
+    if !output.status.success() {
+        return Err(TexTmplError::Rasterize(format!(
+            "{} failed: {}",
+            tool,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+/// Renders the PDF of `TemplateRecipe` and rasterizes its pages at `dpi` into PNG images, one
+/// `Vec<u8>` per page in page order; `recipe.output` is not written
+///
+/// The rasterization runs the `pdftoppm` tool of Poppler, which has to be on the `PATH`
+/// (`poppler-utils` on most Linux distributions); its absence or failure is reported as
+/// `TexTmplError::Rasterize`.
+#[cfg(feature = "png")]
+pub fn render_png<T: serde::Serialize>(
+    recipe: &TemplateRecipe<T>,
+    dpi: u32,
+    pages: PageSelection,
+) -> Result<Vec<Vec<u8>>, TexTmplError> {
+    use std::process::Command;
+
+    let pdf_data = recipe_to_pdf(recipe)?;
+
+    with_temp_dir(&recipe.tectonic, |dir| {
+        let pdf_path = dir.join("input.pdf");
+        write_file(&pdf_path, &pdf_data)?;
+
+        let mut command = Command::new("pdftoppm");
+        command.arg("-png").arg("-r").arg(dpi.to_string());
+        if pages == PageSelection::First {
+            command.args(["-f", "1", "-l", "1"]);
+        }
+        run_converter(command.arg(&pdf_path).arg(dir.join("page")))?;
+
+        // pdftoppm names the images page-1.png or page-01.png, ... depending on the page count
+        let mut images = Vec::new();
+        for entry in std::fs::read_dir(dir).map_err(TexTmplError::OutputStream)? {
+            let path = entry.map_err(TexTmplError::OutputStream)?.path();
+            let number = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix("page-"))
+                .and_then(|name| name.strip_suffix(".png"))
+                .and_then(|number| number.parse::<usize>().ok());
+            if let Some(number) = number {
+                images.push((number, path));
+            }
+        }
+        images.sort();
+
+        images
+            .into_iter()
+            .map(|(_, path)| std::fs::read(&path).map_err(TexTmplError::OutputStream))
+            .collect()
+    })
+}
+
+/// Renders the PDF of `TemplateRecipe` and converts its pages into SVG documents, one `String`
+/// per page in page order, e.g. for zoomable previews on a web page; `recipe.output` is not
+/// written
+///
+/// The conversion runs the `pdftocairo` tool of Poppler, which has to be on the `PATH`
+/// (`poppler-utils` on most Linux distributions); its absence or failure is reported as
+/// `TexTmplError::Rasterize`. Text is converted into outlines, so the SVG needs no fonts.
+#[cfg(feature = "svg")]
+pub fn render_svg<T: serde::Serialize>(
+    recipe: &TemplateRecipe<T>,
+    pages: PageSelection,
+) -> Result<Vec<String>, TexTmplError> {
+    use std::process::Command;
+
+    let pdf_data = recipe_to_pdf(recipe)?;
+    let page_count = match pages {
+        PageSelection::First => 1,
+        PageSelection::All => count_pages(&pdf_data)?,
+    };
+
+    with_temp_dir(&recipe.tectonic, |dir| {
+        let pdf_path = dir.join("input.pdf");
+        write_file(&pdf_path, &pdf_data)?;
+
+        // pdftocairo writes a single page per SVG file
+        (1..=page_count)
+            .map(|page| {
+                let svg_path = dir.join(format!("page-{}.svg", page));
+                let page = page.to_string();
+                run_converter(
+                    Command::new("pdftocairo")
+                        .arg("-svg")
+                        .args(["-f", &page, "-l", &page])
+                        .arg(&pdf_path)
+                        .arg(&svg_path),
+                )?;
+                read_to_string(&svg_path).map_err(TexTmplError::OutputStream)
+            })
+            .collect()
+    })
+}
+
+/// Outputs PDF from `TemplateRecipe` like `render_pdf`, but fails with `TexTmplError::Timeout`
+/// when the compilation takes longer than `timeout`
+///
+/// The compilation runs on a worker thread which is abandoned on timeout; Rust threads cannot be
+/// killed, so a runaway compilation keeps its thread (and Tectonic's process-wide engine lock)
+/// until it ends. Run untrusted templates in a separate process when that is not acceptable.
+#[cfg(feature = "pdf")]
+pub fn render_pdf_with_timeout<T: serde::Serialize>(
+    recipe: &TemplateRecipe<T>,
+    timeout: Duration,
+) -> Result<(), TexTmplError> {
+    if recipe.keep_output()? {
+        return Ok(());
+    }
+    let tex = prepare_tex(recipe)?;
+
+    let options = recipe.tectonic_options();
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = sender.send(compile_pdf(
+            &tex,
+            &options,
+            &mut NoopStatusBackend::default(),
+        ));
+    });
+
+    let pdf_data = match receiver.recv_timeout(timeout) {
+        Ok(result) => recipe.finish_pdf(result?)?,
+        Err(RecvTimeoutError::Timeout) => return Err(TexTmplError::Timeout(timeout)),
+        Err(RecvTimeoutError::Disconnected) => {
+            return Err(TexTmplError::compile("compilation thread panicked"))
+        }
+    };
+
+    recipe.write_output(&pdf_data)
+}
+
+/// Outputs PDF from `TemplateRecipe` like `render_pdf` and returns a `RenderReport` about it
+#[cfg(feature = "pdf")]
+pub fn render_pdf_reported<T: serde::Serialize>(
+    recipe: &TemplateRecipe<T>,
+) -> Result<RenderReport, TexTmplError> {
+    let tex = prepare_tex(recipe)?;
+
+    let mut status = WarningCollector::default();
+    let pdf_data = compile_recipe(&tex, recipe, &mut status)?;
+    let pages = count_pages(&pdf_data)?;
+
+    recipe.write_output(&pdf_data)?;
+
+    Ok(RenderReport {
+        pages,
+        bytes: pdf_data.len(),
+        warnings: status.warnings,
+    })
+}
+
+/// Outputs PDF from `TemplateRecipe` like `render_pdf` and returns the rendered TeX together with
+/// the PDF bytes; when the TeX fails to compile the error is `TexTmplError::WithTex` holding the
+/// TeX and the compilation error
+#[cfg(feature = "pdf")]
+pub fn render_pdf_with_tex<T: serde::Serialize>(
+    recipe: &TemplateRecipe<T>,
+) -> Result<(String, Vec<u8>), TexTmplError> {
+    let tex = prepare_tex(recipe)?;
+
+    let pdf_data = match compile_recipe(&tex, recipe, &mut NoopStatusBackend::default()) {
+        Ok(pdf_data) => pdf_data,
+        Err(e) => return Err(TexTmplError::WithTex(tex, Box::new(e))),
+    };
+    recipe.write_output(&pdf_data)?;
+
+    Ok((tex, pdf_data))
+}
+
+/// Outputs PDF from `TemplateRecipe` like `render_pdf` and returns its bytes together with every
+/// message Tectonic reported (`warning: ...`, `note: ...`), e.g. overfull boxes or undefined
+/// references; when the compilation fails the messages are appended to the
+/// `TexTmplError::Compile` message, one per line
+#[cfg(feature = "pdf")]
+pub fn render_pdf_verbose<T: serde::Serialize>(
+    recipe: &TemplateRecipe<T>,
+) -> Result<(Vec<u8>, Vec<String>), TexTmplError> {
+    let tex = prepare_tex(recipe)?;
+
+    let mut status = LogCollector::default();
+    let pdf_data = match compile_recipe(&tex, recipe, &mut status) {
+        Ok(pdf_data) => pdf_data,
+        Err(TexTmplError::Compile {
+            line,
+            message,
+            snippet,
+        }) if !status.messages.is_empty() => {
+            return Err(TexTmplError::Compile {
+                line,
+                message: format!("{}\n{}", message, status.messages.join("\n")),
+                snippet,
+            })
+        }
+        Err(e) => return Err(e),
+    };
+
+    recipe.write_output(&pdf_data)?;
+
+    Ok((pdf_data, status.messages))
+}
+
+/// What a batch of recipes does when one of them fails
+#[cfg(feature = "pdf")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BatchMode {
+    /// Stop at the first failing recipe, whose error is the last of the results
+    FailFast,
+    /// Render every recipe regardless of the failures before it
+    #[default]
+    ContinueOnError,
+}
+
+/// Outputs PDF for every `TemplateRecipe` in `recipes` like `render_pdf`, loading the Tectonic
+/// configuration only once; the results line up index-for-index with `recipes`, and with
+/// `BatchMode::FailFast` end at the first `Err`, leaving the recipes after it unrendered
+#[cfg(feature = "pdf")]
+pub fn render_pdf_batch<T: serde::Serialize>(
+    recipes: &[TemplateRecipe<T>],
+    mode: BatchMode,
+) -> Vec<Result<(), TexTmplError>> {
+    render_pdf_batch_with_progress(recipes, mode, |_, _| {})
+}
+
+/// Outputs PDF for every `TemplateRecipe` in `recipes` like `render_pdf_batch` and calls
+/// `on_progress` with `(completed, total)` after each recipe, successful or not, e.g. to drive a
+/// progress bar
+#[cfg(feature = "pdf")]
+pub fn render_pdf_batch_with_progress<T: serde::Serialize, F: FnMut(usize, usize)>(
+    recipes: &[TemplateRecipe<T>],
+    mode: BatchMode,
+    mut on_progress: F,
+) -> Vec<Result<(), TexTmplError>> {
+    let engine = TexEngine::new();
+    let total = recipes.len();
+
+    let mut results = Vec::with_capacity(total);
+    for (i, recipe) in recipes.iter().enumerate() {
+        let result = match &engine {
+            Ok(engine) => engine.render_pdf(recipe),
+            Err(_) => render_pdf(recipe),
+        };
+        on_progress(i + 1, total);
+
+        let failed = result.is_err();
+        results.push(result);
+        if failed && mode == BatchMode::FailFast {
+            break;
+        }
+    }
+    results
+}
+
+/// Outputs PDF for every `TemplateRecipe` in `recipes` like `render_pdf_batch`, but processes the
+/// recipes on the rayon thread pool; every recipe gets its own Tectonic session and the results
+/// still line up index-for-index with `recipes`
+///
+/// Tectonic runs its TeX engine under a process-wide lock, so templating, bundle access and
+/// writing the output happen in parallel while the engine runs themselves are serialized.
+#[cfg(feature = "rayon")]
+pub fn render_pdf_batch_parallel<T: serde::Serialize + Sync>(
+    recipes: &[TemplateRecipe<T>],
+) -> Vec<Result<(), TexTmplError>> {
+    render_pdf_batch_parallel_with_progress(recipes, |_, _| {})
+}
+
+/// Outputs PDF for every `TemplateRecipe` in `recipes` like `render_pdf_batch_parallel` and calls
+/// `on_progress` with `(completed, total)` after each recipe, successful or not
+///
+/// `on_progress` is always called on the calling thread, while the recipes are rendered on the
+/// rayon thread pool, so it needs to be neither `Send` nor `Sync`.
+#[cfg(feature = "rayon")]
+pub fn render_pdf_batch_parallel_with_progress<T, F>(
+    recipes: &[TemplateRecipe<T>],
+    mut on_progress: F,
+) -> Vec<Result<(), TexTmplError>>
+where
+    T: serde::Serialize + Sync,
+    F: FnMut(usize, usize),
+{
+    use rayon::prelude::*;
+
+    let total = recipes.len();
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        let worker = scope.spawn(move || {
+            let engine = TexEngine::new();
+            recipes
+                .par_iter()
+                .map(|recipe| {
+                    let result = match &engine {
+                        Ok(engine) => engine.render_pdf(recipe),
+                        Err(_) => render_pdf(recipe),
+                    };
+                    let _ = sender.send(());
+                    result
+                })
+                .collect()
+        });
+
+        // the channel closes once the worker has finished and dropped the sender
+        for (completed, ()) in receiver.iter().enumerate() {
+            on_progress(completed + 1, total);
+        }
+
+        worker
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+/// Writes PDF from `TemplateRecipe` into `writer` using Tectonic and returns the number of bytes
+/// written; `recipe.output` is ignored
+#[cfg(feature = "pdf")]
+pub fn render_pdf_to_writer<T: serde::Serialize, W: Write>(
+    recipe: &TemplateRecipe<T>,
+    writer: &mut W,
+) -> Result<usize, TexTmplError> {
+    let pdf_data = recipe_to_pdf(recipe)?;
+
+    writer
+        .write_all(&pdf_data)
+        .and_then(|_| writer.flush())
+        .map_err(TexTmplError::OutputStream)?;
+
+    Ok(pdf_data.len())
+}
+
+/// Concatenates the PDF documents in `inputs` into one document with their pages in order
+///
+/// Only the pages and what they reference are kept; the outlines, named destinations and
+/// document-level settings of the inputs are dropped. An empty `inputs` gives a document without
+/// pages.
+#[cfg(feature = "pdf")]
+pub fn concat_pdfs(inputs: &[&[u8]]) -> Result<Vec<u8>, TexTmplError> {
+    use lopdf::{dictionary, Document, Object};
+
+    // page attributes a page inherits from its ancestors in the page tree
+    const INHERITED: [&[u8]; 4] = [b"Resources", b"MediaBox", b"CropBox", b"Rotate"];
+
+    let mut merged = Document::with_version("1.5");
+    let pages_id = merged.new_object_id();
+    let mut kids = Vec::new();
+
+    for input in inputs {
+        let mut document = Document::load_mem(input).map_err(TexTmplError::Pdf)?;
+        document.renumber_objects_with(merged.max_id + 1);
+
+        let page_ids: Vec<_> = document.get_pages().into_values().collect();
+        for &page_id in &page_ids {
+            let mut inherited = Vec::new();
+            let mut parent = document
+                .get_dictionary(page_id)
+                .and_then(|page| page.get(b"Parent"))
+                .and_then(Object::as_reference)
+                .ok();
+            // bounded by the object count in case of a cyclic page tree
+            for _ in 0..document.objects.len() {
+                let Some(node) = parent.and_then(|id| document.get_dictionary(id).ok()) else {
+                    break;
+                };
+                for key in INHERITED {
+                    if let Ok(value) = node.get(key) {
+                        inherited.push((key, value.clone()));
+                    }
+                }
+                parent = node.get(b"Parent").and_then(Object::as_reference).ok();
+            }
+
+            let page = document
+                .get_object_mut(page_id)
+                .and_then(Object::as_dict_mut)
+                .map_err(TexTmplError::Pdf)?;
+            // the nearest ancestor comes first and wins
+            for (key, value) in inherited {
+                if !page.has(key) {
+                    page.set(key, value);
+                }
+            }
+            page.set("Parent", pages_id);
+            kids.push(Object::Reference(page_id));
+        }
+
+        for (id, object) in document.objects {
+            match object.type_name() {
+                Ok(b"Catalog" | b"Pages" | b"Outlines" | b"Outline" | b"ObjStm" | b"XRef") => {}
+                _ => {
+                    merged.objects.insert(id, object);
+                }
+            }
+        }
+        merged.max_id = merged.max_id.max(document.max_id);
+    }
+
+    let count = kids.len() as i64;
+    merged.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => kids,
+            "Count" => count,
+        }),
+    );
+    let catalog_id = merged.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+    });
+    merged.trailer.set("Root", catalog_id);
+
+    let mut pdf = Vec::new();
+    merged
+        .save_to(&mut pdf)
+        .map_err(TexTmplError::OutputStream)?;
+    Ok(pdf)
+}
+
+/// Writes the TeX prepared from `TemplateRecipe` into `tex_path` and returns it
+pub fn render_tex<T: serde::Serialize>(
+    recipe: &TemplateRecipe<T>,
+    tex_path: &Path,
+) -> Result<String, TexTmplError> {
+    let tex = prepare_tex::<T>(recipe)?;
+
+    write_file(tex_path, tex.as_bytes())?;
+    Ok(tex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    /// Data for `{{foo}}` which TeX typesets with an overfull box, so Tectonic reports that
+    /// "warnings were issued by the TeX engine"
+    #[cfg(feature = "pdf")]
+    const OVERFULL_HBOX: &str = r"\hbox to 10pt{\hskip 50pt}";
+
+    /// Writes a document with `{{foo}}` as its body to `dir` and returns the paths of the
+    /// template and of the PDF to render it to
+    #[cfg(feature = "pdf")]
+    fn write_foo_document(dir: &Path) -> (PathBuf, PathBuf) {
+        let tex_path = dir.join("test.tex");
+        std::fs::write(
+            &tex_path,
+            "\\documentclass{article}\n\\begin{document}\n{{foo}}\n\\end{document}\n",
+        )
+        .unwrap();
+        (tex_path, dir.join("test.pdf"))
+    }
+
+    #[test]
+    fn test_render_tex() {
+        let latex_input = r#"
+            \documentclass{article}
+            \begin{document}
+                Hello, {{foo}}!
+            \end{document}
+        "#;
+        let latex_output = r#"
+            \documentclass{article}
+            \begin{document}
+                Hello, boo!
+            \end{document}
+        "#;
+
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+
+        {
+            let mut file = File::create(&tex_path).expect("Temp TeX cannot be created");
+            write!(file, "{}", latex_input).unwrap();
+        }
+
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .build()
+            .unwrap();
+
+        let output = prepare_tex(&t);
+
+        assert_eq!(output.unwrap(), latex_output);
+
+        let rendered_path = dir.path().join("rendered.tex");
+        assert_eq!(render_tex(&t, &rendered_path).unwrap(), latex_output);
+        assert_eq!(
+            std::fs::read_to_string(&rendered_path).unwrap(),
+            latex_output
+        );
+    }
+
+    #[test]
+    fn test_prepare_tex_from_str() {
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        let output = prepare_tex_from_str(r"\textbf{Hello, {{foo}}!}", &data, None);
+
+        assert_eq!(output.unwrap(), r"\textbf{Hello, boo!}");
+    }
+
+    #[test]
+    fn test_prepare_tex_from_reader() {
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        let template: &[u8] = br"\textbf{Hello, {{foo}}!}";
+        let output = prepare_tex_from_reader(template, &data, None);
+        assert_eq!(output.unwrap(), r"\textbf{Hello, boo!}");
+
+        let invalid: &[u8] = b"Hello, \xff{{foo}}";
+        assert!(matches!(
+            prepare_tex_from_reader(invalid, &data, None),
+            Err(TexTmplError::TemplateStream(_))
+        ));
+    }
+
+    #[test]
+    fn test_json_value() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        std::fs::write(
+            &tex_path,
+            r"{{#with customer}}{{name}} ({{address.city}}, {{lookup address.lines 1}}){{/with}}
+{{#each orders}}{{@index}}:{{id}}{{#each items}} {{sku}}x{{qty}}@{{../id}}{{/each}};{{/each}}
+{{#each totals}}{{@key}}={{this}} {{/each}}
+{{#each matrix}}[{{#each this}}{{this}}{{#unless @last}},{{/unless}}{{/each}}]{{/each}}
+{{#if paid}}paid{{else}}open{{/if}} {{#if note}}{{note}}{{else}}no note{{/if}} {{missing.deep}}|
+{{price}} {{count}} {{customer.name}}",
+        )
+        .unwrap();
+
+        let data = serde_json::json!({
+            "customer": {
+                "name": "Boo & Co",
+                "address": {"city": "Zagreb", "lines": ["Ilica 1", "2nd floor"]}
+            },
+            "orders": [
+                {"id": "A1", "items": [{"sku": "x_1", "qty": 2}, {"sku": "y", "qty": 1}]},
+                {"id": "B2", "items": []}
+            ],
+            "totals": {"net": 10.5, "tax": 2},
+            "matrix": [[1, 2], [3]],
+            "paid": false,
+            "note": null,
+            "price": 1.5,
+            "count": 18446744073709551615u64
+        });
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&tex_path)
+            .data(&data)
+            .escape(EscapeMode::Latex)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            prepare_tex(&t).unwrap(),
+            "Boo \\& Co (Zagreb, 2nd floor)\n\
+             0:A1 x\\_1x2@A1 yx1@A1;1:B2;\n\
+             net=10.5 tax=2 \n\
+             [1,2][3]\n\
+             open no note |\n\
+             1.5 18446744073709551615 Boo \\& Co"
+        );
+    }
+
+    #[test]
+    fn test_top_level_array() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        std::fs::write(
+            &tex_path,
+            r"{{#each this}}{{@index}}:{{name}}{{#if @first}} first{{/if}}{{#if @last}} last{{/if}};{{/each}}
+{{#each this}}{{this.name}}/{{@root.[1].name}} {{/each}}|{{#with (lookup this 0)}}{{name}}{{/with}}|
+{{this.[1].name}} {{[0].name}} {{len this}} [{{@index}}]",
+        )
+        .unwrap();
+
+        let data = serde_json::json!([{"name": "R&D"}, {"name": "Ops"}]);
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .data(&data)
+            .helpers(helpers::default_helpers())
+            .escape(EscapeMode::Latex)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            prepare_tex(&t).unwrap(),
+            "0:R\\&D first;1:Ops last;\nR\\&D/Ops Ops/Ops |R\\&D|\nOps R\\&D 2 []"
+        );
+
+        std::fs::write(&tex_path, "{{this}} [{{@index}}{{#each this}}x{{/each}}]").unwrap();
+        let scalar = serde_json::json!("Boo & Co");
+        let t = TemplateRecipe { data: &scalar, ..t };
+        assert_eq!(prepare_tex(&t).unwrap(), "Boo \\& Co []");
+    }
+
+    #[test]
+    fn test_render_html_like() {
+        let mut data = HashMap::new();
+        data.insert("foo", r"\emph{<b>&amp;</b>}");
+
+        let output = prepare_tex_from_str("{{foo}}", &data, None);
+
+        assert_eq!(output.unwrap(), r"\emph{<b>&amp;</b>}");
+    }
+
+    #[test]
+    fn test_latex_escape() {
+        assert_eq!(
+            latex_escape(r"50% & $5 #1 a_b {x} ~ ^ \"),
+            r"50\% \& \$5 \#1 a\_b \{x\} \textasciitilde{} \textasciicircum{} \textbackslash{}"
+        );
+    }
+
+    #[test]
+    fn test_validate_tex() {
+        assert!(validate_tex(r"\textbf{a \{ b} $x^{2}$ % unmatched } in a comment").is_ok());
+        assert!(validate_tex(
+            r"\newcommand{\pair}[2]{#1 & #2}\def\twice#1{#1##1}
+\begin{tabular}{ll} R\&D & \#1 \\ \end{tabular} $\begin{pmatrix} a & b \end{pmatrix}$
+\verb|a & {#| \begin{verbatim} } & # \end{verbatim}"
+        )
+        .is_ok());
+
+        let errors = [
+            (r"\textbf{x}}", 10, "unmatched }"),
+            (r"\section{Intro \textbf{x}", 8, "unclosed {"),
+            (r"{a {b} {c", 0, "unclosed {"),
+            (r"Issue #5", 6, "unescaped #"),
+            (r"\def\x{y} #1", 10, "unescaped #"),
+            (r"R&D", 1, "unescaped & outside of an alignment"),
+            (
+                r"\begin{tabular}{l}a&b\end{tabular} c&d",
+                36,
+                "unescaped & outside of an alignment",
+            ),
+            (r"costs \$5 and $x", 14, "unclosed $"),
+            ("ćevapi {", 7, "unclosed {"),
+        ];
+        for (tex, offset, message) in errors {
+            match validate_tex(tex) {
+                Err(TexTmplError::Validation(o, m)) => {
+                    assert_eq!((o, m.as_str()), (offset, message), "{}", tex)
+                }
+                _ => panic!("Expected a Validation error for {}", tex),
+            }
+        }
+
+        let dir = tempdir().expect("Temp dir cannot be created");
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+        let mut tex_file = File::create(&tex_path).unwrap();
+        write!(tex_file, "{{{{foo}}}}").unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("foo", r"\textbf{x}}");
+
+        let mut t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .build()
+            .unwrap();
+        assert_eq!(prepare_tex(&t).unwrap(), r"\textbf{x}}");
+
+        t.validate = true;
+        assert!(matches!(
+            prepare_tex(&t),
+            Err(TexTmplError::Validation(10, _))
+        ));
+        #[cfg(feature = "pdf")]
+        {
+            assert!(matches!(
+                render_pdf(&t),
+                Err(TexTmplError::Validation(10, _))
+            ));
+            assert!(!pdf_path.exists());
+        }
+    }
+
+    #[test]
+    fn test_require_placeholders() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+        let mut tex_file = File::create(&tex_path).unwrap();
+        write!(
+            tex_file,
+            r"\textbf{{static}} {{{{!-- a comment --}}}} \{{{{foo}}}}"
+        )
+        .unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        let mut t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .build()
+            .unwrap();
+        assert_eq!(prepare_tex(&t).unwrap(), r"\textbf{static}  {{foo}}");
+
+        t.require_placeholders = true;
+        match prepare_tex(&t) {
+            Err(TexTmplError::Validation(0, message)) => {
+                assert!(message.contains("no Handlebars expressions"))
+            }
+            _ => panic!("Expected a Validation error"),
+        }
+
+        let mut tex_file = File::create(&tex_path).unwrap();
+        write!(tex_file, r"\textbf{{ {{{{~foo~}}}} }}").unwrap();
+        assert_eq!(prepare_tex(&t).unwrap(), r"\textbf{boo}");
+    }
+
+    #[test]
+    fn test_encoding() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+        // "Café {{foo}}" in Latin-1
+        std::fs::write(&tex_path, b"Caf\xe9 {{foo}}").unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        let mut t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .build()
+            .unwrap();
+        assert!(matches!(
+            prepare_tex(&t),
+            Err(TexTmplError::TemplateRead(..))
+        ));
+
+        t.encoding = Some(encoding_rs::WINDOWS_1252);
+        assert_eq!(prepare_tex(&t).unwrap(), "Café boo");
+    }
+
+    #[test]
+    fn test_crlf_template() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        std::fs::write(
+            &tex_path,
+            "\\documentclass{article}\r\n\\begin{verbatim}\r\n{{foo}}\r\n\\end{verbatim}\r\n",
+        )
+        .unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&tex_path)
+            .data(&data)
+            .build()
+            .unwrap();
+        let expected = "\\documentclass{article}\n\\begin{verbatim}\nboo\n\\end{verbatim}\n";
+        assert_eq!(prepare_tex(&t).unwrap(), expected);
+
+        let latin1 = TemplateRecipe {
+            encoding: Some(encoding_rs::WINDOWS_1252),
+            ..t
+        };
+        assert_eq!(prepare_tex(&latin1).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_template_name() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+        let mut tex_file = File::create(&tex_path).unwrap();
+        write!(tex_file, "{{{{missing}}}}").unwrap();
+
+        let data: HashMap<&str, &str> = HashMap::new();
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .strict(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            CompiledTemplate::from_recipe(&t).unwrap().name(),
+            "tex_template"
+        );
+
+        let t = TemplateRecipe {
+            template_name: Some("invoice.tex".to_string()),
+            ..t
+        };
+        assert_eq!(
+            CompiledTemplate::from_recipe(&t).unwrap().name(),
+            "invoice.tex"
+        );
+        assert!(prepare_tex(&t)
+            .unwrap_err()
+            .to_string()
+            .contains("invoice.tex"));
+    }
+
+    #[test]
+    fn test_packages() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+        let mut tex_file = File::create(&tex_path).unwrap();
+        let latex = r#"\documentclass[a4paper]{article}
+\usepackage[T1]{fontenc}
+\usepackage{graphicx, xcolor}
+\begin{document}{{foo}}\end{document}"#;
+        write!(tex_file, "{}", latex).unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .package("hyperref")
+            .package("xcolor")
+            .package("booktabs")
+            .package("hyperref")
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            prepare_tex(&t).unwrap(),
+            r#"\documentclass[a4paper]{article}
+\usepackage{hyperref}
+\usepackage{booktabs}
+\usepackage[T1]{fontenc}
+\usepackage{graphicx, xcolor}
+\begin{document}boo\end{document}"#
+        );
+
+        let mut tex_file = File::create(&tex_path).unwrap();
+        let latex = r#"% \documentclass{letter}
+\documentclass{article}
+%\usepackage{hyperref}
+\usepackage{xcolor,% for the headings
+  booktabs}
+\begin{document}{{foo}}\end{document}"#;
+        write!(tex_file, "{}", latex).unwrap();
+        assert_eq!(
+            prepare_tex(&t).unwrap(),
+            r#"% \documentclass{letter}
+\documentclass{article}
+\usepackage{hyperref}
+%\usepackage{hyperref}
+\usepackage{xcolor,% for the headings
+  booktabs}
+\begin{document}boo\end{document}"#
+        );
+        assert_eq!(
+            command_argument(r"\title{A {\bf b} \} c} d", 6),
+            Some((7, 21))
+        );
+
+        let mut tex_file = File::create(&tex_path).unwrap();
+        write!(tex_file, "{{{{foo}}}}").unwrap();
+        assert!(matches!(
+            prepare_tex(&t),
+            Err(TexTmplError::Validation(0, _))
+        ));
+    }
+
+    #[test]
+    fn test_required_packages() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let mut tex_file = File::create(&tex_path).unwrap();
+        write!(
+            tex_file,
+            r"\documentclass{{article}}
+\begin{{document}}{{{{href url}}}}\end{{document}}"
+        )
+        .unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("url", "https://example.com");
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&tex_path)
+            .data(&data)
+            .helpers(helpers::default_helpers())
+            .package("booktabs")
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            prepare_tex(&t).unwrap(),
+            "\\documentclass{article}\n\\usepackage{booktabs}\n\\usepackage{hyperref}\n\
+             \\begin{document}\\url{https://example.com}\\end{document}"
+        );
+
+        let plain = TemplateRecipe {
+            packages: Vec::new(),
+            ..t
+        };
+        let mut tex_file = File::create(&tex_path).unwrap();
+        write!(tex_file, r"\documentclass{{article}}{{{{upper url}}}}").unwrap();
+        assert_eq!(
+            prepare_tex(&plain).unwrap(),
+            "\\documentclass{article}HTTPS://EXAMPLE.COM"
+        );
+
+        let mut tex_file = File::create(&tex_path).unwrap();
+        write!(tex_file, "{{{{href url}}}}").unwrap();
+        assert_eq!(prepare_tex(&plain).unwrap(), "\\url{https://example.com}");
+
+        let mut tex_file = File::create(&tex_path).unwrap();
+        write!(
+            tex_file,
+            "% input by \\documentclass{{book}}\n{{{{href url}}}}"
+        )
+        .unwrap();
+        assert_eq!(
+            prepare_tex(&plain).unwrap(),
+            "% input by \\documentclass{book}\n\\url{https://example.com}"
+        );
+    }
+
+    #[test]
+    fn test_documentclass_options() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let mut tex_file = File::create(&tex_path).unwrap();
+        write!(
+            tex_file,
+            r"\documentclass[{{{{fontsize}}}},{{{{papersize}}}}]{{article}}
+\begin{{document}}{{{{title}}}}\end{{document}}"
+        )
+        .unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("fontsize", "11pt");
+        data.insert("papersize", "a4paper");
+        data.insert("title", "R&D");
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .data(&data)
+            .escape(EscapeMode::Latex)
+            .strict(true)
+            .package("booktabs")
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            prepare_tex(&t).unwrap(),
+            "\\documentclass[11pt,a4paper]{article}\n\\usepackage{booktabs}\n\
+             \\begin{document}R\\&D\\end{document}"
+        );
+
+        let mut missing = data.clone();
+        missing.remove("fontsize");
+        let t = TemplateRecipe {
+            data: &missing,
+            ..t
+        };
+        match prepare_tex(&t) {
+            Err(e @ TexTmplError::Render(_)) => assert!(e.to_string().contains("fontsize")),
+            other => panic!("Expected a Render error, got {:?}", other.map(|_| ())),
+        }
+
+        let lenient = TemplateRecipe { strict: false, ..t };
+        assert!(prepare_tex(&lenient)
+            .unwrap()
+            .starts_with("\\documentclass[,a4paper]{article}"));
+    }
+
+    #[test]
+    fn test_escape_helper() {
+        let mut data = HashMap::new();
+        data.insert("foo", "R&D 100%");
+
+        let output = prepare_tex_from_str("{{escape foo}} {{foo}}", &data, None);
+
+        assert_eq!(output.unwrap(), r"R\&D 100\% R&D 100%");
+    }
+
+    #[test]
+    fn test_escape_mode_latex() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+
+        {
+            let mut file = File::create(&tex_path).expect("Temp TeX cannot be created");
+            write!(file, "{{{{foo}}}} {{{{{{bar}}}}}}").unwrap();
+        }
+
+        let mut data = HashMap::new();
+        data.insert("foo", "R&D");
+        data.insert("bar", r"\textbf{raw}");
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .escape(EscapeMode::Latex)
+            .build()
+            .unwrap();
+
+        let output = prepare_tex(&t);
+
+        assert_eq!(output.unwrap(), r"R\&D \textbf{raw}");
+    }
+
+    #[test]
+    fn test_escape_mode_custom() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let mut tex_file = File::create(&tex_path).unwrap();
+        write!(tex_file, "{{{{foo}}}} {{{{{{foo}}}}}}").unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("foo", "R&D ACME");
+
+        fn trademark(s: &str) -> String {
+            latex_escape(s).replace("ACME", r"ACME\texttrademark{}")
+        }
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&tex_path)
+            .data(&data)
+            .escape(EscapeMode::Custom(trademark))
+            .build()
+            .unwrap();
+        assert_eq!(
+            prepare_tex(&t).unwrap(),
+            r"R\&D ACME\texttrademark{} R&D ACME"
+        );
+
+        let raw = TemplateRecipe {
+            escape: EscapeMode::None,
+            ..t
+        };
+        assert_eq!(prepare_tex(&raw).unwrap(), "R&D ACME R&D ACME");
+    }
+
+    #[test]
+    fn test_compiled_template() {
+        let template = CompiledTemplate::new("Hello, {{foo}}!", None).unwrap();
+
+        for name in ["boo", "zoo"] {
+            let mut data = HashMap::new();
+            data.insert("foo", name);
+
+            assert_eq!(template.render(&data).unwrap(), format!("Hello, {}!", name));
+        }
+
+        let helpers = default_helpers();
+        let template = CompiledTemplate::new("{{foo}} {{percent bar}}", Some(&helpers)).unwrap();
+        let rows = [
+            serde_json::json!({"foo": "a", "bar": 0.1}),
+            serde_json::json!({"foo": "b", "bar": "none"}),
+            serde_json::json!({"foo": "c", "bar": 0.5}),
+        ];
+        let results = template.render_all(&rows);
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_deref().unwrap(), "a 10.00\\%");
+        assert!(matches!(results[1], Err(TexTmplError::Render(_))));
+        assert_eq!(results[2].as_deref().unwrap(), "c 50.00\\%");
+    }
+
+    #[test]
+    fn test_closure_helper() {
+        let mut translations = HashMap::new();
+        translations.insert("hello".to_string(), "Bok".to_string());
+
+        let translate = move |h: &Helper,
+                              _: &Handlebars,
+                              _: &Context,
+                              _: &mut RenderContext,
+                              out: &mut dyn Output|
+              -> HelperResult {
+            let key = h.param(0).and_then(|p| p.value().as_str()).unwrap_or("");
+            out.write(translations.get(key).map_or(key, |t| t.as_str()))?;
+            Ok(())
+        };
+
+        let helpers = vec![
+            HandlebarsHelper::new("t", translate),
+            ("upper".to_string(), helpers::upper).into(),
+        ];
+        let data: HashMap<&str, &str> = HashMap::new();
+
+        let output = prepare_tex_from_str(
+            r#"{{t "hello"}}, {{upper (t "world")}}"#,
+            &data,
+            Some(&helpers),
+        );
+
+        assert_eq!(output.unwrap(), "Bok, WORLD");
+    }
+
+    #[test]
+    fn test_prepare_tex_all() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let cover_path = dir.path().join("cover.tex");
+        let mut cover_file = File::create(&cover_path).unwrap();
+        write!(cover_file, r"\title{{ {{{{~upper title~}}}} }}").unwrap();
+
+        let body_path = dir.path().join("body.tex");
+        let mut body_file = File::create(&body_path).unwrap();
+        write!(body_file, r"{{{{> cover}}}} \section{{ {{{{~title~}}}} }}").unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("title", "Report");
+
+        let templates = vec![
+            ("cover".to_string(), cover_path),
+            ("body".to_string(), body_path),
+        ];
+        let output = prepare_tex_all(&templates, &data, Some(&helpers::default_helpers())).unwrap();
+
+        assert_eq!(output.len(), 2);
+        assert_eq!(output["cover"], r"\title{REPORT}");
+        assert_eq!(output["body"], r"\title{REPORT} \section{Report}");
+
+        let templates = vec![("missing".to_string(), dir.path().join("missing.tex"))];
+        assert!(matches!(
+            prepare_tex_all(&templates, &data, None),
+            Err(TexTmplError::TemplateRead(..))
+        ));
+    }
+
+    #[test]
+    fn test_write_tex_streaming() {
+        let mut data = HashMap::new();
+        data.insert("currency", "EUR");
+
+        let rows = (1..=10_000).map(|i| {
+            let mut row = HashMap::new();
+            row.insert("price", i);
+            row
+        });
+
+        let mut tex = Vec::new();
+        write_tex_streaming(
+            r"{{#each_row}}{{#if @first}}first {{/if}}{{@index}}:{{price}} {{../currency}}{{#unless @last}}, {{/unless}}{{/each_row}}",
+            &data,
+            rows,
+            None,
+            &mut tex,
+        )
+        .unwrap();
+
+        let tex = String::from_utf8(tex).unwrap();
+        assert!(tex.starts_with("first 0:1 EUR, 1:2 EUR, "));
+        assert!(tex.ends_with(", 9999:10000 EUR"));
+        assert_eq!(tex.matches("EUR").count(), 10_000);
+    }
+
+    #[test]
+    fn test_duplicate_helper() {
+        let data: HashMap<&str, &str> = HashMap::new();
+
+        let mut helpers = helpers::default_helpers();
+        helpers.push(HandlebarsHelper::new("upper", helpers::lower));
+
+        match prepare_tex_from_str("{{upper \"boo\"}}", &data, Some(&helpers)) {
+            Err(TexTmplError::DuplicateHelper(name)) => assert_eq!(name, "upper"),
+            _ => panic!("Expected a DuplicateHelper error"),
+        }
+
+        for name in ["escape", "if", "lookup"] {
+            let shadowing = [HandlebarsHelper::new(name, helpers::upper)];
+            match prepare_tex_from_str("{{escape \"&\"}}", &data, Some(&shadowing)) {
+                Err(TexTmplError::DuplicateHelper(n)) => assert_eq!(n, name),
+                _ => panic!("Expected a DuplicateHelper error for {}", name),
+            }
+        }
+        assert_eq!(
+            prepare_tex_from_str("{{escape \"&\"}}", &data, Some(&helpers::default_helpers()))
+                .unwrap(),
+            "\\&"
+        );
+    }
+
+    #[test]
+    fn test_configure_registry() {
+        struct Repeat;
+
+        impl HelperDef for Repeat {
+            fn call<'reg: 'rc, 'rc>(
+                &self,
+                h: &Helper<'reg, 'rc>,
+                _: &'reg Handlebars<'reg>,
+                _: &'rc Context,
+                _: &mut RenderContext<'reg, 'rc>,
+                out: &mut dyn Output,
+            ) -> HelperResult {
+                let value = h.param(0).and_then(|p| p.value().as_str()).unwrap_or("");
+                out.write(&value.repeat(2))?;
+                Ok(())
+            }
+        }
+
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+        let mut tex_file = File::create(&tex_path).unwrap();
+        write!(tex_file, "{{{{repeat foo}}}} {{{{missing}}}}").unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .configure_registry(|registry| {
+                registry.register_helper("repeat", Box::new(Repeat));
+                registry.set_strict_mode(true);
+            })
+            .build()
+            .unwrap();
+
+        assert!(matches!(prepare_tex(&t), Err(TexTmplError::Render(_))));
+
+        let mut tex_file = File::create(&tex_path).unwrap();
+        write!(tex_file, "{{{{repeat foo}}}}").unwrap();
+        assert_eq!(prepare_tex(&t).unwrap(), "booboo");
+    }
+
+    #[test]
+    fn test_template_variables() {
+        let template = r#"
+            \textbf{ {{~customer.name~}} } {{customer/address}}
+            {{#each items as |item|}}
+                {{name}}: {{currency price symbol=../currency}} {{@index}} {{item.sku}}
+                {{#if discount}}{{this.discount}}{{/if}}
+            {{else}}
+                {{empty_message}}
+            {{/each}}
+            {{#with totals}}{{net}}{{@root.vat}}{{/with}}
+            {{upper (lower title)}}
+        "#;
+
+        assert_eq!(
+            template_variables(template).unwrap(),
+            vec![
+                "currency",
+                "customer.address",
+                "customer.name",
+                "empty_message",
+                "items",
+                "items.discount",
+                "items.name",
+                "items.price",
+                "items.sku",
+                "title",
+                "totals",
+                "totals.net",
+                "vat",
+            ]
+        );
+
+        assert!(matches!(
+            template_variables("{{#each items}}"),
+            Err(TexTmplError::Template(_))
+        ));
+    }
+
+    #[test]
+    fn test_partials() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let header_path = dir.path().join("header.tex");
+        let missing_path = dir.path().join("missing.tex");
+        let pdf_path = dir.path().join("test.pdf");
+
+        {
+            let mut file = File::create(&tex_path).expect("Temp TeX cannot be created");
+            write!(file, "{{{{> header}}}}Hello, {{{{foo}}}}!").unwrap();
+            let mut file = File::create(&header_path).expect("Temp TeX cannot be created");
+            write!(file, r"\title{{Report for {{{{foo}}}}}} ").unwrap();
+        }
+
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        let mut t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .partial("header", header_path)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            prepare_tex(&t).unwrap(),
+            r"\title{Report for boo} Hello, boo!"
+        );
+
+        t.partials = Some(vec![("footer".to_string(), missing_path)]);
+
+        match prepare_tex(&t) {
+            Err(TexTmplError::Partial(name, _)) => assert_eq!(name, "footer"),
+            _ => panic!("Expected a Partial error"),
+        }
+    }
+
+    #[test]
+    fn test_layout() {
+        let layout = r#"\documentclass{article}
+\begin{document}
+{{> @partial-block}}
+\end{document}"#;
+
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let layout_path = dir.path().join("layout.tex");
+        let pdf_path = dir.path().join("test.pdf");
+
+        {
+            let mut file = File::create(&tex_path).expect("Temp TeX cannot be created");
+            write!(file, "Hello, {{{{foo}}}}!").unwrap();
+            let mut file = File::create(&layout_path).expect("Temp TeX cannot be created");
+            write!(file, "{}", layout).unwrap();
+        }
+
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .layout(&layout_path)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            prepare_tex(&t).unwrap(),
+            "\\documentclass{article}\n\\begin{document}\nHello, boo!\\end{document}"
+        );
+    }
+
+    #[test]
+    fn test_delimiters() {
+        let latex_input = r#"\newcommand{\pair}[2]{{{#1}}{{#2}}}
+\pair{<<foo>>}{<<{bar}>>}\<<baz>><<#if baz>>!<</if>>"#;
+        let latex_output = r#"\newcommand{\pair}[2]{{{#1}}{{#2}}}
+\pair{R&D}{\textbf{raw}}\relax!"#;
+
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+
+        {
+            let mut file = File::create(&tex_path).expect("Temp TeX cannot be created");
+            write!(file, "{}", latex_input).unwrap();
+        }
+
+        let mut data = HashMap::new();
+        data.insert("foo", "R&D");
+        data.insert("bar", r"\textbf{raw}");
+        data.insert("baz", "relax");
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .delimiters("<<", ">>")
+            .build()
+            .unwrap();
+
+        assert_eq!(prepare_tex(&t).unwrap(), latex_output);
+    }
+
+    #[test]
+    fn test_strict_mode() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+
+        {
+            let mut file = File::create(&tex_path).expect("Temp TeX cannot be created");
+            write!(file, "Hello, {{{{foo}}}}{{{{missing}}}}!").unwrap();
+        }
+
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        let mut t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .build()
+            .unwrap();
+
+        assert_eq!(prepare_tex(&t).unwrap(), "Hello, boo!");
+
+        t.strict = true;
+
+        assert!(matches!(prepare_tex(&t), Err(TexTmplError::Render(_))));
+    }
+
+    #[test]
+    fn test_missing_template() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("missing.tex");
+        let pdf_path = dir.path().join("test.pdf");
+
+        let data: HashMap<&str, &str> = HashMap::new();
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .build()
+            .unwrap();
+
+        match prepare_tex(&t) {
+            Err(TexTmplError::TemplateRead(path, _)) => assert_eq!(path, tex_path),
+            _ => panic!("Expected a TemplateRead error"),
+        }
+    }
+
+    #[test]
+    fn test_builder() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+        let mut tex_file = File::create(&tex_path).unwrap();
+        write!(tex_file, "{{{{upper foo}}}} & {{{{bar}}}}").unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+        data.insert("bar", "$5");
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .helper(("upper", helpers::upper))
+            .escape(EscapeMode::Latex)
+            .build()
+            .unwrap();
+
+        assert_eq!(prepare_tex(&t).unwrap(), r"BOO & \$5");
+
+        match TemplateRecipe::<HashMap<&str, &str>>::builder()
+            .template(&tex_path)
+            .build()
+        {
+            Err(e @ TexTmplError::MissingField("data")) => {
+                assert_eq!(e.to_string(), "Missing required recipe field data")
+            }
+            _ => panic!("Expected a MissingField error"),
+        }
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .data(&data)
+            .helper(("upper", helpers::upper))
+            .build()
+            .unwrap();
+
+        assert!(t.output.is_none());
+        assert_eq!(prepare_tex(&t).unwrap(), "BOO & $5");
+        #[cfg(feature = "pdf")]
+        assert!(matches!(
+            render_pdf(&t),
+            Err(TexTmplError::MissingField("output"))
+        ));
+
+        let shared = helpers::default_helpers();
+        let recipes: Vec<_> = (0..3)
+            .map(|_| {
+                TemplateRecipe::builder()
+                    .template(&tex_path)
+                    .data(&data)
+                    .shared_helpers(&shared)
+                    .build()
+                    .unwrap()
+            })
+            .collect();
+        for t in &recipes {
+            assert!(matches!(t.helpers, Some(Cow::Borrowed(_))));
+            assert_eq!(prepare_tex(t).unwrap(), "BOO & $5");
+        }
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .data(&data)
+            .shared_helpers(&shared)
+            .helper(("bar", helpers::lower))
+            .build()
+            .unwrap();
+        assert_eq!(t.helpers.unwrap().len(), shared.len() + 1);
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_render_pdf() {
+        let latex = r#"
+            \documentclass{article}
+            \begin{document}
+                Hello, {{foo}}!
+            \end{document}
+        "#;
+
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+
+        {
+            let mut file = File::create(&tex_path).expect("Temp TeX cannot be created");
+            write!(file, "{}", latex).unwrap();
+        }
+
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .build()
+            .unwrap();
+
+        let _ = render_pdf(&t);
+
+        {
+            let file = File::open(&pdf_path).expect("Temp TeX cannot be opened");
+            assert_eq!(file.metadata().unwrap().len(), 2767);
+        }
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_render_pdf_to_bytes() {
+        let latex = r#"
+            \documentclass{article}
+            \begin{document}
+                Hello, {{foo}}!
+            \end{document}
+        "#;
+
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+
+        {
+            let mut file = File::create(&tex_path).expect("Temp TeX cannot be created");
+            write!(file, "{}", latex).unwrap();
+        }
+
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        let pdf = render_pdf_to_bytes(&tex_path, &data, None).unwrap();
+
+        assert!(pdf.starts_with(b"%PDF"));
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_render_str_to_pdf() {
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        let pdf = render_str_to_pdf(
+            r"\documentclass{article}\begin{document}{{upper foo}}\newpage 2\end{document}",
+            &data,
+            Some(&helpers::default_helpers()),
+        )
+        .unwrap();
+        assert!(pdf.starts_with(b"%PDF"));
+        assert_eq!(count_pages(&pdf).unwrap(), 2);
+
+        // \href is only defined once the hyperref package the helper requires is loaded
+        data.insert("url", "https://example.com");
+        let template = r"\documentclass{article}\begin{document}{{href url foo}}\end{document}";
+        assert_eq!(
+            prepare_tex_to_compile(template, &data, Some(&helpers::default_helpers())).unwrap(),
+            "\\documentclass{article}\n\\usepackage{hyperref}\
+             \\begin{document}\\href{https://example.com}{boo}\\end{document}"
+        );
+        let pdf = render_str_to_pdf(template, &data, Some(&helpers::default_helpers())).unwrap();
+        assert!(pdf.starts_with(b"%PDF"));
+
+        assert!(matches!(
+            render_str_to_pdf(r"{{foo", &data, None),
+            Err(TexTmplError::Template(_))
+        ));
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_render_xdv() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+        let mut tex_file = File::create(&tex_path).unwrap();
+        write!(tex_file, r"\documentclass{{article}} {{{{foo}}}}").unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .build()
+            .unwrap();
+
+        // every XDV file starts with the DVI preamble opcode
+        assert_eq!(render_xdv(&t).unwrap().first(), Some(&0xf7));
+        assert!(!pdf_path.exists());
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_render_pdf_to_writer() {
+        let latex = r#"
+            \documentclass{article}
+            \begin{document}
+                Hello, {{foo}}!
+            \end{document}
+        "#;
+
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+
+        {
+            let mut file = File::create(&tex_path).expect("Temp TeX cannot be created");
+            write!(file, "{}", latex).unwrap();
+        }
+
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .build()
+            .unwrap();
+
+        let mut buffer = Vec::new();
+        let written = render_pdf_to_writer(&t, &mut buffer).unwrap();
+
+        assert_eq!(written, buffer.len());
+        assert!(buffer.starts_with(b"%PDF"));
+        assert!(!pdf_path.exists());
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_render_pdf_reported() {
+        let latex = r#"
+            \documentclass{article}
+            \begin{document}
+                Hello, {{foo}}!
+                \newpage
+                Bye, {{foo}}!
+            \end{document}
+        "#;
+
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+
+        {
+            let mut file = File::create(&tex_path).expect("Temp TeX cannot be created");
+            write!(file, "{}", latex).unwrap();
+        }
+
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .build()
+            .unwrap();
+
+        let report = render_pdf_reported(&t).unwrap();
+
+        assert_eq!(report.pages, 2);
+        assert_eq!(report.bytes as u64, pdf_path.metadata().unwrap().len());
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_render_pdf_verbose() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+        let (tex_path, pdf_path) = write_foo_document(dir.path());
+
+        let mut data = HashMap::new();
+        data.insert("foo", OVERFULL_HBOX);
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .build()
+            .unwrap();
+
+        let (pdf, log) = render_pdf_verbose(&t).unwrap();
+        assert!(pdf.starts_with(b"%PDF"));
+        assert_eq!(std::fs::read(&pdf_path).unwrap(), pdf);
+        assert!(log
+            .iter()
+            .any(|m| m.starts_with("warning: warnings were issued by the TeX engine")));
+
+        let mut failing = HashMap::new();
+        failing.insert("foo", r"\undefinedcommand");
+        let t = TemplateRecipe {
+            data: &failing,
+            ..t
+        };
+        match render_pdf_verbose(&t) {
+            Err(TexTmplError::Compile { message, .. }) => assert!(message.contains("\nerror: ")),
+            _ => panic!("Expected a Compile error"),
+        }
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_verbosity() {
+        assert!(Verbosity::default().printer().is_none());
+        assert!(Verbosity::Warnings.printer().is_some());
+
+        let dir = tempdir().expect("Temp dir cannot be created");
+        let (tex_path, pdf_path) = write_foo_document(dir.path());
+
+        let mut data = HashMap::new();
+        data.insert("foo", OVERFULL_HBOX);
+
+        let t = TemplateRecipe {
+            tectonic: TectonicOptions {
+                verbosity: Verbosity::Verbose,
+                ..TectonicOptions::default()
+            },
+            ..TemplateRecipe::builder()
+                .template(&tex_path)
+                .output(&pdf_path)
+                .data(&data)
+                .build()
+                .unwrap()
+        };
+
+        let (pdf, log) = render_pdf_verbose(&t).unwrap();
+        assert!(pdf.starts_with(b"%PDF"));
+        assert!(log
+            .iter()
+            .any(|m| m.starts_with("warning: warnings were issued by the TeX engine")));
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_create_dirs() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let pdf_dir = dir.path().join("out").join("2024").join("03");
+        let pdf_path = pdf_dir.join("test.pdf");
+        let mut tex_file = File::create(&tex_path).unwrap();
+        write!(tex_file, r"\documentclass{{article}} {{{{foo}}}}").unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        let mut t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .build()
+            .unwrap();
+
+        match render_pdf(&t) {
+            Err(e @ TexTmplError::OutputWrite(..)) => {
+                assert!(e.to_string().contains(&pdf_dir.display().to_string()))
+            }
+            _ => panic!("Expected an OutputWrite error"),
+        }
+
+        t.create_dirs = true;
+        render_pdf(&t).unwrap();
+        assert!(pdf_path.exists());
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_warnings_as_errors() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+        let mut tex_file = File::create(&tex_path).unwrap();
+        write!(
+            tex_file,
+            r"\documentclass{{article}}\begin{{document}}\hbox to 10pt{{\hskip 50pt}}\end{{document}}"
+        )
+        .unwrap();
+
+        let data: HashMap<&str, &str> = HashMap::new();
+        let lenient = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .build()
+            .unwrap();
+        render_pdf(&lenient).unwrap();
+
+        let strict = TemplateRecipe {
+            tectonic: TectonicOptions {
+                warnings_as_errors: true,
+                ..TectonicOptions::default()
+            },
+            ..lenient.clone()
+        };
+        match render_pdf(&strict) {
+            Err(TexTmplError::Warnings(warnings)) => {
+                assert_eq!(warnings.len(), 1);
+                assert!(warnings[0].starts_with("Overfull \\hbox"));
+            }
+            other => panic!("Expected a Warnings error, got {:?}", other),
+        }
+
+        let other_pattern = TemplateRecipe {
+            tectonic: TectonicOptions {
+                warnings_as_errors: true,
+                warning_pattern: Some("Underfull".to_string()),
+                ..TectonicOptions::default()
+            },
+            ..lenient
+        };
+        render_pdf(&other_pattern).unwrap();
+
+        assert_eq!(
+            log_warnings(
+                "LaTeX Warning: Reference `x' undefined.\nUnderfull \\vbox (badness 10000)\nfine",
+                None
+            ),
+            [
+                "LaTeX Warning: Reference `x' undefined.",
+                "Underfull \\vbox (badness 10000)"
+            ]
+        );
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_render_both() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let template_path = dir.path().join("template.tex");
+        let tex_path = dir.path().join("out").join("report.tex");
+        let pdf_path = dir.path().join("out").join("report.pdf");
+        let mut template_file = File::create(&template_path).unwrap();
+        write!(
+            template_file,
+            r"\documentclass{{article}}\begin{{document}}{{{{{{foo}}}}}}\end{{document}}"
+        )
+        .unwrap();
+
+        let mut failing = HashMap::new();
+        failing.insert("foo", r"\undefinedcommand");
+
+        let t = TemplateRecipe::builder()
+            .template(&template_path)
+            .output(&pdf_path)
+            .data(&failing)
+            .create_dirs(true)
+            .build()
+            .unwrap();
+
+        assert!(matches!(
+            render_both(&t, &tex_path),
+            Err(TexTmplError::Compile { .. })
+        ));
+        assert!(!dir.path().join("out").exists());
+
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+        let t = TemplateRecipe { data: &data, ..t };
+
+        render_both(&t, &tex_path).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&tex_path).unwrap(),
+            r"\documentclass{article}\begin{document}boo\end{document}"
+        );
+        assert!(std::fs::read(&pdf_path).unwrap().starts_with(b"%PDF"));
+        assert_eq!(
+            std::fs::read_dir(dir.path().join("out")).unwrap().count(),
+            2
+        );
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_overwrite() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+        let mut tex_file = File::create(&tex_path).unwrap();
+        write!(tex_file, r"\documentclass{{article}} {{{{foo}}}}").unwrap();
+        std::fs::write(&pdf_path, "previous").unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        let mut t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .overwrite(OverwritePolicy::Skip)
+            .build()
+            .unwrap();
+
+        render_pdf(&t).unwrap();
+        assert_eq!(std::fs::read(&pdf_path).unwrap(), b"previous");
+
+        t.overwrite = OverwritePolicy::Error;
+        match render_pdf(&t) {
+            Err(TexTmplError::OutputExists(path)) => assert_eq!(path, pdf_path),
+            _ => panic!("Expected an OutputExists error"),
+        }
+        assert_eq!(std::fs::read(&pdf_path).unwrap(), b"previous");
+
+        t.overwrite = OverwritePolicy::Replace;
+        render_pdf(&t).unwrap();
+        assert!(std::fs::read(&pdf_path).unwrap().starts_with(b"%PDF"));
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_compile_error_line() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+        let mut tex_file = File::create(&tex_path).unwrap();
+        write!(
+            tex_file,
+            r"\documentclass{{article}}\begin{{document}}{{{{foo}}}}\end{{document}}"
+        )
+        .unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("foo", r"\undefinedcommand");
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .build()
+            .unwrap();
+
+        let e = render_pdf(&t).unwrap_err();
+        assert!(e.to_string().starts_with("Cannot compile TeX at line 1: "));
+        match e {
+            TexTmplError::Compile {
+                message,
+                line: Some(1),
+                snippet,
+            } => {
+                assert!(message.contains("Undefined control sequence"));
+                assert_eq!(
+                    snippet.as_deref(),
+                    Some(
+                        r">    1 | \documentclass{article}\begin{document}\undefinedcommand\end{document}"
+                    )
+                );
+            }
+            _ => panic!("Expected a Compile error with a line"),
+        }
+
+        let mut status = NoopStatusBackend::default();
+        let mut locator = ErrorLocator {
+            inner: &mut status,
+            error: None,
+        };
+        locator
+            .dump_error_logs(b"(texput.tex\n! Missing $ inserted.\n<inserted text>\nl.4 a_\n   b");
+        assert_eq!(locator.error, Some((4, "Missing $ inserted.".to_string())));
+
+        let tex = "a\nb\nc\nd\ne\nf";
+        assert_eq!(
+            tex_snippet(tex, 2),
+            "     1 | a\n>    2 | b\n     3 | c\n     4 | d"
+        );
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_render_pdf_with_tex() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+        let mut tex_file = File::create(&tex_path).unwrap();
+        write!(tex_file, r"\documentclass{{article}} {{{{{{foo}}}}}}").unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .build()
+            .unwrap();
+
+        let (tex, pdf) = render_pdf_with_tex(&t).unwrap();
+        assert_eq!(tex, r"\documentclass{article} boo");
+        assert_eq!(pdf, std::fs::read(&pdf_path).unwrap());
+
+        let mut failing = HashMap::new();
+        failing.insert("foo", r"\undefinedcommand");
+        let t = TemplateRecipe {
+            data: &failing,
+            ..t
+        };
+        match render_pdf_with_tex(&t) {
+            Err(TexTmplError::WithTex(tex, e)) => {
+                assert_eq!(tex, r"\documentclass{article} \undefinedcommand");
+                assert!(matches!(*e, TexTmplError::Compile { .. }));
+            }
+            _ => panic!("Expected a WithTex error"),
+        }
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_local_bundle() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+        let mut tex_file = File::create(&tex_path).unwrap();
+        let latex = r#"
+            \documentclass{article}
+            \begin{document}
+            {{foo}}
+            \end{document}
+        "#;
+        write!(tex_file, "{}", latex).unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .tectonic(TectonicOptions {
+                bundle: BundleSource::Local(dir.path().join("missing-bundle.zip")),
+                only_cached: true,
+                ..TectonicOptions::default()
+            })
+            .build()
+            .unwrap();
+
+        assert!(matches!(render_pdf(&t), Err(TexTmplError::Compile { .. })));
+        assert!(!pdf_path.exists());
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_intermediates_dir() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+        let mut tex_file = File::create(&tex_path).unwrap();
+        let latex = r#"
+            \documentclass{article}
+            \begin{document}
+            {{foo}}
+            \end{document}
+        "#;
+        write!(tex_file, "{}", latex).unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .tectonic(TectonicOptions {
+                intermediates_dir: Some(dir.path().to_path_buf()),
+                ..TectonicOptions::default()
+            })
+            .build()
+            .unwrap();
+
+        render_pdf(&t).unwrap();
+
+        assert!(pdf_path.exists());
+        assert!(dir.path().join("texput.aux").exists());
+        assert!(dir.path().join("texput.log").exists());
+        assert!(!dir.path().join("texput.pdf").exists());
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_temp_dir() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+        let temp_path = dir.path().join("temp");
+        let mut tex_file = File::create(&tex_path).unwrap();
+        write!(tex_file, r"\documentclass{{article}} {{{{foo}}}}").unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        let mut t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .tectonic(TectonicOptions {
+                temp_dir: Some(temp_path.clone()),
+                ..TectonicOptions::default()
+            })
+            .build()
+            .unwrap();
+
+        render_pdf(&t).unwrap();
+        assert!(pdf_path.exists());
+        assert_eq!(std::fs::read_dir(&temp_path).unwrap().count(), 0);
+
+        t.tectonic.intermediates_dir = Some(dir.path().to_path_buf());
+        render_pdf(&t).unwrap();
+        let kept: Vec<_> = std::fs::read_dir(&temp_path).unwrap().collect();
+        assert_eq!(kept.len(), 1);
+        assert!(kept[0].as_ref().unwrap().path().join("texput.pdf").exists());
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_shell_escape_disabled() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+        let marker_path = dir.path().join("marker");
+        let mut tex_file = File::create(&tex_path).unwrap();
+        write!(
+            tex_file,
+            r"\documentclass{{article}}
+            \begin{{document}}
+            \immediate\write18{{touch {{{{marker}}}} }}
+            Hello
+            \end{{document}}"
+        )
+        .unwrap();
+
+        let marker = marker_path.display().to_string();
+        let mut data = HashMap::new();
+        data.insert("marker", marker.as_str());
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .build()
+            .unwrap();
+
+        // TeX only notes the command in its log instead of running it
+        render_pdf(&t).unwrap();
+        assert!(!marker_path.exists());
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_engine_options() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+        let mut tex_file = File::create(&tex_path).unwrap();
+        write!(tex_file, r"\documentclass{{article}} {{{{foo}}}}").unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        assert_eq!(
+            TectonicOptions::default().engine_options,
+            EngineOptions {
+                shell_escape: false,
+                format: None,
+            }
+        );
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .tectonic(TectonicOptions {
+                engine_options: EngineOptions {
+                    shell_escape: true,
+                    format: Some("latex".to_string()),
+                },
+                ..TectonicOptions::default()
+            })
+            .build()
+            .unwrap();
+
+        render_pdf(&t).unwrap();
+        assert!(pdf_path.exists());
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_reruns() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+        let mut tex_file = File::create(&tex_path).unwrap();
+        let latex = r#"
+            \documentclass{article}
+            \begin{document}
+            \tableofcontents
+            \section{Intro}
+            See section~\ref{sec:end} on page~\pageref{sec:end}.
+            \newpage
+            \section{ {{~title~}} }\label{sec:end}
+            \end{document}
+        "#;
+        write!(tex_file, "{}", latex).unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("title", "End");
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .tectonic(TectonicOptions {
+                reruns: Some(2),
+                ..TectonicOptions::default()
+            })
+            .build()
+            .unwrap();
+
+        render_pdf(&t).unwrap();
+
+        let pdf = lopdf::Document::load(&pdf_path).unwrap();
+        let pages: Vec<u32> = pdf.get_pages().keys().copied().collect();
+        assert_eq!(pages.len(), 2);
+        assert!(!pdf.extract_text(&pages).unwrap().contains("??"));
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_bibliography() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+        let bib_dir = dir.path().join("bib");
+        std::fs::create_dir(&bib_dir).unwrap();
+        let bib_path = bib_dir.join("refs.bib");
+
+        let mut tex_file = File::create(&tex_path).unwrap();
+        let latex = r#"
+            \documentclass{article}
+            \begin{document}
+            {{foo}} is described in~\cite{knuth84}.
+            \bibliographystyle{plain}
+            \bibliography{refs}
+            \end{document}
+        "#;
+        write!(tex_file, "{}", latex).unwrap();
+
+        let mut bib_file = File::create(&bib_path).unwrap();
+        let bib = r#"
+            @book{knuth84,
+              author = {Donald E. Knuth},
+              title = {The {\TeX}book},
+              publisher = {Addison-Wesley},
+              year = {1984}
+            }
+        "#;
+        write!(bib_file, "{}", bib).unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("foo", "TeX");
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .tectonic(TectonicOptions {
+                bibliography: Some(bib_path.clone()),
+                ..TectonicOptions::default()
+            })
+            .build()
+            .unwrap();
+
+        let (_, log) = render_pdf_verbose(&t).unwrap();
+        assert!(!log.iter().any(|m| m.contains("ignored due to security")));
+
+        let pdf = lopdf::Document::load(&pdf_path).unwrap();
+        let pages: Vec<u32> = pdf.get_pages().keys().copied().collect();
+        assert!(!pdf.extract_text(&pages).unwrap().contains("[?]"));
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_input_relative_to_template() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+        std::fs::create_dir(dir.path().join("chapters")).unwrap();
+
+        let mut tex_file = File::create(&tex_path).unwrap();
+        let latex = r#"
+            \documentclass{article}
+            \begin{document}
+            {{foo}}
+            \input{chapters/intro}
+            \end{document}
+        "#;
+        write!(tex_file, "{}", latex).unwrap();
+
+        let mut chapter_file = File::create(dir.path().join("chapters/intro.tex")).unwrap();
+        write!(chapter_file, "\\section{{Introduction}}").unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .build()
+            .unwrap();
+
+        render_pdf(&t).unwrap();
+        assert!(pdf_path.exists());
+
+        let elsewhere = tempdir().expect("Temp dir cannot be created");
+        let t = TemplateRecipe {
+            tectonic: TectonicOptions {
+                working_dir: Some(elsewhere.path().to_path_buf()),
+                ..TectonicOptions::default()
+            },
+            ..t
+        };
+        assert!(matches!(render_pdf(&t), Err(TexTmplError::Compile { .. })));
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_asset_dirs() {
+        // a 1x1 red PNG
+        const LOGO: [u8; 69] = [
+            0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48,
+            0x44, 0x52, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x02, 0x00, 0x00,
+            0x00, 0x90, 0x77, 0x53, 0xde, 0x00, 0x00, 0x00, 0x0c, 0x49, 0x44, 0x41, 0x54, 0x78,
+            0x9c, 0x63, 0xf8, 0xcf, 0xc0, 0x00, 0x00, 0x03, 0x01, 0x01, 0x00, 0xc9, 0xfe, 0x92,
+            0xef, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+        ];
+
+        let dir = tempdir().expect("Temp dir cannot be created");
+        let assets = tempdir().expect("Temp dir cannot be created");
+        std::fs::write(assets.path().join("logo.png"), LOGO).unwrap();
+
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+        let mut tex_file = File::create(&tex_path).unwrap();
+        let latex = r#"
+            \documentclass{article}
+            \usepackage{graphicx}
+            \begin{document}
+            {{foo}}
+            \includegraphics{logo.png}
+            \end{document}
+        "#;
+        write!(tex_file, "{}", latex).unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        let mut t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .build()
+            .unwrap();
+
+        assert!(matches!(render_pdf(&t), Err(TexTmplError::Compile { .. })));
+
+        t.tectonic.asset_dirs = vec![assets.path().to_path_buf()];
+        render_pdf(&t).unwrap();
+        assert!(pdf_path.exists());
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_render_pdf_from_json() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let json_path = dir.path().join("test.json");
+        let pdf_path = dir.path().join("test.pdf");
+
+        let mut tex_file = File::create(&tex_path).unwrap();
+        let latex = r#"
+            \documentclass{article}
+            \begin{document}
+            {{#each items}}{{name}}: {{price}}\par{{/each}}
+            \end{document}
+        "#;
+        write!(tex_file, "{}", latex).unwrap();
+
+        let mut json_file = File::create(&json_path).unwrap();
+        write!(json_file, r#"{{"items": [{{"name": "boo", "price": 5}}]}}"#).unwrap();
+
+        render_pdf_from_json(&tex_path, &json_path, &pdf_path).unwrap();
+        assert!(pdf_path.exists());
+
+        let mut json_file = File::create(&json_path).unwrap();
+        write!(json_file, r#"{{"items": ["#).unwrap();
+
+        match render_pdf_from_json(&tex_path, &json_path, &pdf_path) {
+            Err(TexTmplError::Data(path, _)) => assert_eq!(path, json_path),
+            _ => panic!("Expected a Data error"),
+        }
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn test_yaml_data() {
+        let dir = tempdir().expect("Temp dir cannot be created");
 
-/// A recipe for `render_pdf` which specifies an input template path, an output PDF path, data in
-/// form of mapping (`Serialize`able) and an optional vector of `HandlebarsHelper`
-#[derive(Clone)]
-pub struct TemplateRecipe<'a, T: serde::Serialize> {
-    pub template: &'a Path,
-    pub output: &'a Path,
-    pub data: &'a T,
-    pub helpers: Option<Vec<HandlebarsHelper>>,
-}
+        let tex_path = dir.path().join("test.tex");
+        let yaml_path = dir.path().join("test.yaml");
+        #[cfg(feature = "pdf")]
+        let pdf_path = dir.path().join("test.pdf");
 
-/// Outputs TeX from `TemplateRecipe`
-pub fn prepare_tex<T: serde::Serialize>(
-    recipe: &TemplateRecipe<T>,
-) -> Result<String, Box<dyn Error>> {
-    let mut hb_reg = Handlebars::new();
-    let template_name = "tex_template";
+        let mut tex_file = File::create(&tex_path).unwrap();
+        write!(tex_file, "{{{{title}}}}\n{{{{body}}}}").unwrap();
 
-    if let Some(helpers) = &recipe.helpers {
-        for h in helpers {
-            let (n, f) = h;
-            hb_reg.register_helper(n, Box::new(f));
+        let mut yaml_file = File::create(&yaml_path).unwrap();
+        let yaml = "# the report title\n\
+                    title: Report # trailing comment\n\
+                    body: |\n  First line\n  Second line\n";
+        write!(yaml_file, "{}", yaml).unwrap();
+
+        assert_eq!(
+            prepare_tex_from_yaml(&tex_path, &yaml_path).unwrap(),
+            "Report\nFirst line\nSecond line\n"
+        );
+
+        #[cfg(feature = "pdf")]
+        {
+            render_pdf_from_yaml(&tex_path, &yaml_path, &pdf_path).unwrap();
+            assert!(pdf_path.exists());
         }
+
+        let mut yaml_file = File::create(&yaml_path).unwrap();
+        write!(yaml_file, "title: [").unwrap();
+        assert!(matches!(
+            prepare_tex_from_yaml(&tex_path, &yaml_path),
+            Err(TexTmplError::Data(..))
+        ));
     }
 
-    let tex_content = read_to_string(recipe.template).expect("Cannot read template file");
+    #[cfg(feature = "csv")]
+    #[test]
+    fn test_load_csv() {
+        let dir = tempdir().expect("Temp dir cannot be created");
 
-    hb_reg.register_template_string(template_name, tex_content)?;
+        let csv_path = dir.path().join("test.csv");
+        let mut csv_file = File::create(&csv_path).unwrap();
+        write!(csv_file, "name,price\nboo,5\n\"zoo, large\",12\n").unwrap();
 
-    Ok(hb_reg.render(template_name, recipe.data)?)
-}
+        let data = load_csv(&csv_path).unwrap();
+        let output = prepare_tex_from_str(
+            r"{{#each rows}}{{name}} & {{price}} \\ {{/each}}",
+            &data,
+            None,
+        );
+        assert_eq!(output.unwrap(), r"boo & 5 \\ zoo, large & 12 \\ ");
 
-/// Outputs PDF from `TemplateRecipe` using `tectonic::latex_to_pdf`
-pub fn render_pdf<T: serde::Serialize>(recipe: &TemplateRecipe<T>) -> Result<(), Box<dyn Error>> {
-    let tex = prepare_tex::<T>(recipe)?;
+        let mut csv_file = File::create(&csv_path).unwrap();
+        write!(csv_file, "name,price\nboo\n").unwrap();
+        assert!(matches!(load_csv(&csv_path), Err(TexTmplError::Data(..))));
+    }
 
-    let pdf_data: Vec<u8> = tectonic::latex_to_pdf(&tex)?;
-    let mut file = File::create(&recipe.output)?;
-    file.write_all(&pdf_data)?;
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_check() {
+        let dir = tempdir().expect("Temp dir cannot be created");
 
-    Ok(())
-}
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+        let mut tex_file = File::create(&tex_path).unwrap();
+        let latex = r#"
+            \documentclass{article}
+            \begin{document}
+            {{{foo}}}
+            \end{document}
+        "#;
+        write!(tex_file, "{}", latex).unwrap();
 
-/// Outputs TeX and PDF from `TemplateRecipe` using `tectonic::latex_to_pdf`
-pub fn render_tex<T: serde::Serialize>(
-    recipe: &TemplateRecipe<T>,
-    tex_path: &Path,
-) -> Result<(), Box<dyn Error>> {
-    let tex = prepare_tex::<T>(recipe)?;
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
 
-    let mut tex_file = File::create(tex_path)?;
-    tex_file.write_all(&tex.as_bytes())?;
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .build()
+            .unwrap();
 
-    Ok(())
-}
+        check(&t).unwrap();
+        assert!(!pdf_path.exists());
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::collections::HashMap;
-    use std::fs::File;
-    use std::io::Write;
-    use tempfile::tempdir;
+        let mut failing = HashMap::new();
+        failing.insert("foo", r"\undefinedcommand");
+        let t = TemplateRecipe {
+            data: &failing,
+            ..t
+        };
+        assert!(matches!(check(&t), Err(TexTmplError::Compile { .. })));
+    }
 
+    #[cfg(feature = "digest")]
     #[test]
-    fn test_render_tex() {
-        let latex_input = r#"
+    fn test_prepare_tex_digest() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let mut tex_file = File::create(&tex_path).unwrap();
+        write!(tex_file, "a{{{{foo}}}}").unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("foo", "bc");
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .data(&data)
+            .build()
+            .unwrap();
+
+        let (tex, digest) = prepare_tex_digest(&t).unwrap();
+        assert_eq!(tex, "abc");
+        assert_eq!(digest.bytes, 3);
+        assert_eq!(
+            digest.sha256,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+
+        let mut changed = data.clone();
+        changed.insert("foo", "bd");
+        let t = TemplateRecipe {
+            data: &changed,
+            ..t
+        };
+        assert_ne!(prepare_tex_digest(&t).unwrap().1, digest);
+    }
+
+    #[cfg(feature = "cache")]
+    #[test]
+    fn test_render_pdf_cached() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+        let mut tex_file = File::create(&tex_path).unwrap();
+        let latex = r#"
             \documentclass{article}
             \begin{document}
-                Hello, {{foo}}!
+            {{foo}}
             \end{document}
         "#;
-        let latex_output = r#"
+        write!(tex_file, "{}", latex).unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .build()
+            .unwrap();
+
+        let cache = PdfCache::new(dir.path().join("cache"));
+        render_pdf_cached(&t, &cache).unwrap();
+        let cached: Vec<_> = std::fs::read_dir(cache.dir())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(cached.len(), 1);
+        assert_eq!(
+            std::fs::read(&cached[0]).unwrap(),
+            std::fs::read(&pdf_path).unwrap()
+        );
+
+        // a hit is served from the cache without compiling
+        std::fs::write(&cached[0], b"cached").unwrap();
+        render_pdf_cached(&t, &cache).unwrap();
+        assert_eq!(std::fs::read(&pdf_path).unwrap(), b"cached");
+
+        let mut other = HashMap::new();
+        other.insert("foo", "zoo");
+        render_pdf_cached(
+            &TemplateRecipe {
+                data: &other,
+                ..t.clone()
+            },
+            &cache,
+        )
+        .unwrap();
+        assert_eq!(std::fs::read_dir(cache.dir()).unwrap().count(), 2);
+
+        cache.clear().unwrap();
+        assert_eq!(std::fs::read_dir(cache.dir()).unwrap().count(), 0);
+        render_pdf_cached(&t, &cache).unwrap();
+        assert!(std::fs::read(&pdf_path).unwrap().starts_with(b"%PDF"));
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_concat_pdfs() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+        let mut tex_file = File::create(&tex_path).unwrap();
+        let latex = r#"
             \documentclass{article}
             \begin{document}
-                Hello, boo!
+            {{#each pages}}{{this}}\newpage{{/each}}
+            Last page
+            \end{document}
+        "#;
+        write!(tex_file, "{}", latex).unwrap();
+
+        let mut pdfs = Vec::new();
+        for pages in [vec!["One"], vec!["One", "Two"]] {
+            let mut data = HashMap::new();
+            data.insert("pages", pages);
+
+            let t = TemplateRecipe::builder()
+                .template(&tex_path)
+                .output(&pdf_path)
+                .data(&data)
+                .build()
+                .unwrap();
+
+            let mut pdf = Vec::new();
+            render_pdf_to_writer(&t, &mut pdf).unwrap();
+            pdfs.push(pdf);
+        }
+        assert_eq!(count_pages(&pdfs[0]).unwrap(), 2);
+        assert_eq!(count_pages(&pdfs[1]).unwrap(), 3);
+
+        let merged = concat_pdfs(&[&pdfs[0], &pdfs[1]]).unwrap();
+        assert_eq!(count_pages(&merged).unwrap(), 5);
+
+        let empty = concat_pdfs(&[]).unwrap();
+        assert_eq!(count_pages(&empty).unwrap(), 0);
+
+        assert!(matches!(
+            concat_pdfs(&[&pdfs[0], b"not a PDF"]),
+            Err(TexTmplError::Pdf(_))
+        ));
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_metadata() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+        let mut tex_file = File::create(&tex_path).unwrap();
+        let latex = r#"
+            \documentclass{article}
+            \begin{document}
+            {{foo}}
+            \end{document}
+        "#;
+        write!(tex_file, "{}", latex).unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        let title = r"Šibenik (draft) \ 100% & {more}";
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .metadata(PdfMetadata {
+                title: Some(title.to_string()),
+                author: Some("Boo (Zoo)".to_string()),
+                keywords: vec!["report".to_string(), "2024".to_string()],
+                ..PdfMetadata::default()
+            })
+            .build()
+            .unwrap();
+
+        render_pdf(&t).unwrap();
+
+        let pdf = lopdf::Document::load(&pdf_path).unwrap();
+        let info_id = pdf.trailer.get(b"Info").unwrap().as_reference().unwrap();
+        let info = pdf.get_dictionary(info_id).unwrap();
+        let entry = |key: &[u8]| lopdf::decode_text_string(info.get(key).unwrap()).unwrap();
+
+        assert_eq!(entry(b"Title"), title);
+        assert_eq!(entry(b"Author"), "Boo (Zoo)");
+        assert_eq!(entry(b"Keywords"), "report, 2024");
+        assert!(info.get(b"Subject").is_err());
+        assert_eq!(pdf.get_pages().len(), 1);
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_reproducible() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+        let mut tex_file = File::create(&tex_path).unwrap();
+        let latex = r#"
+            \documentclass{article}
+            \begin{document}
+            {{foo}} \today
+            \end{document}
+        "#;
+        write!(tex_file, "{}", latex).unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .tectonic(TectonicOptions {
+                reproducible: true,
+                ..TectonicOptions::default()
+            })
+            .build()
+            .unwrap();
+
+        let mut first = Vec::new();
+        render_pdf_to_writer(&t, &mut first).unwrap();
+        thread::sleep(Duration::from_millis(1100));
+        let mut second = Vec::new();
+        render_pdf_to_writer(&t, &mut second).unwrap();
+
+        assert_eq!(first, second);
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn test_render_png() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+        let mut tex_file = File::create(&tex_path).unwrap();
+        let latex = r#"
+            \documentclass{article}
+            \begin{document}
+            {{foo}}
+            \newpage
+            {{foo}}
             \end{document}
         "#;
+        write!(tex_file, "{}", latex).unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .build()
+            .unwrap();
+
+        // without Poppler installed only the error can be checked
+        match render_png(&t, 36, PageSelection::All) {
+            Ok(images) => {
+                assert_eq!(images.len(), 2);
+                assert!(images.iter().all(|image| image.starts_with(b"\x89PNG")));
+                assert_eq!(render_png(&t, 36, PageSelection::First).unwrap().len(), 1);
+            }
+            Err(TexTmplError::Rasterize(msg)) => assert!(msg.contains("pdftoppm")),
+            Err(e) => panic!("Expected PNG images or a Rasterize error, got {}", e),
+        }
+        assert!(!pdf_path.exists());
+    }
 
+    #[cfg(feature = "svg")]
+    #[test]
+    fn test_render_svg() {
         let dir = tempdir().expect("Temp dir cannot be created");
 
         let tex_path = dir.path().join("test.tex");
         let pdf_path = dir.path().join("test.pdf");
+        let mut tex_file = File::create(&tex_path).unwrap();
+        let latex = r#"
+            \documentclass{article}
+            \begin{document}
+            {{foo}}
+            \newpage
+            {{foo}}
+            \end{document}
+        "#;
+        write!(tex_file, "{}", latex).unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .build()
+            .unwrap();
+
+        // without Poppler installed only the error can be checked
+        match render_svg(&t, PageSelection::All) {
+            Ok(images) => {
+                assert_eq!(images.len(), 2);
+                assert!(images.iter().all(|image| image.contains("<svg")));
+                assert_eq!(render_svg(&t, PageSelection::First).unwrap().len(), 1);
+            }
+            Err(TexTmplError::Rasterize(msg)) => assert!(msg.contains("pdftocairo")),
+            Err(e) => panic!("Expected SVG images or a Rasterize error, got {}", e),
+        }
+        assert!(!pdf_path.exists());
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_shared_engine() {
+        fn assert_send_sync<E: Send + Sync>(_: &E) {}
+
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let mut tex_file = File::create(&tex_path).unwrap();
+        let latex = r#"
+            \documentclass{article}
+            \begin{document}
+            {{foo}}
+            \end{document}
+        "#;
+        write!(tex_file, "{}", latex).unwrap();
+
+        let engine = Arc::new(TexEngine::new().unwrap());
+        assert_send_sync(&engine);
+
+        let handles: Vec<_> = ["boo", "zoo", "koo"]
+            .into_iter()
+            .map(|foo| {
+                let engine = Arc::clone(&engine);
+                let tex_path = tex_path.clone();
+                let pdf_path = dir.path().join(format!("{}.pdf", foo));
+                thread::spawn(move || {
+                    let mut data = HashMap::new();
+                    data.insert("foo", foo);
+
+                    let t = TemplateRecipe::builder()
+                        .template(&tex_path)
+                        .output(&pdf_path)
+                        .data(&data)
+                        .build()
+                        .unwrap();
+                    engine.render_pdf(&t).map(|_| pdf_path)
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            assert!(handle.join().unwrap().unwrap().exists());
+        }
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_render_pdf_batch() {
+        let latex = r#"
+            \documentclass{article}
+            \begin{document}
+                Hello, {{foo}}!
+            \end{document}
+        "#;
+
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let missing_path = dir.path().join("missing.tex");
+        let pdf_paths: Vec<_> = (0..3)
+            .map(|i| dir.path().join(format!("test{}.pdf", i)))
+            .collect();
 
         {
             let mut file = File::create(&tex_path).expect("Temp TeX cannot be created");
-            write!(file, "{}", latex_input).unwrap();
+            write!(file, "{}", latex).unwrap();
         }
 
         let mut data = HashMap::new();
         data.insert("foo", "boo");
 
-        let t = TemplateRecipe {
-            template: &tex_path,
-            output: &pdf_path,
-            data: &data,
-            helpers: None,
-        };
+        let recipes: Vec<_> = pdf_paths
+            .iter()
+            .enumerate()
+            .map(|(i, pdf_path)| {
+                TemplateRecipe::builder()
+                    .template(if i == 1 { &missing_path } else { &tex_path })
+                    .output(pdf_path)
+                    .data(&data)
+                    .build()
+                    .unwrap()
+            })
+            .collect();
 
-        let output = prepare_tex(&t);
+        let mut progress = Vec::new();
+        let results =
+            render_pdf_batch_with_progress(&recipes, BatchMode::FailFast, |done, total| {
+                progress.push((done, total))
+            });
 
-        assert_eq!(output.unwrap(), latex_output);
+        assert_eq!(progress, [(1, 3), (2, 3)]);
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(TexTmplError::TemplateRead(_, _))));
+        assert!(pdf_paths[0].exists() && !pdf_paths[1].exists() && !pdf_paths[2].exists());
+
+        let mut progress = Vec::new();
+        let results =
+            render_pdf_batch_with_progress(&recipes, BatchMode::ContinueOnError, |done, total| {
+                progress.push((done, total))
+            });
+
+        assert_eq!(progress, [(1, 3), (2, 3), (3, 3)]);
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(TexTmplError::TemplateRead(_, _))));
+        assert!(results[2].is_ok());
+        assert!(pdf_paths[0].exists() && !pdf_paths[1].exists() && pdf_paths[2].exists());
     }
 
+    #[cfg(feature = "rayon")]
     #[test]
-    fn test_render_pdf() {
+    fn test_render_pdf_batch_parallel() {
         let latex = r#"
             \documentclass{article}
             \begin{document}
@@ -132,7 +5581,9 @@ mod tests {
         let dir = tempdir().expect("Temp dir cannot be created");
 
         let tex_path = dir.path().join("test.tex");
-        let pdf_path = dir.path().join("test.pdf");
+        let pdf_paths: Vec<_> = (0..8)
+            .map(|i| dir.path().join(format!("test{}.pdf", i)))
+            .collect();
 
         {
             let mut file = File::create(&tex_path).expect("Temp TeX cannot be created");
@@ -142,18 +5593,95 @@ mod tests {
         let mut data = HashMap::new();
         data.insert("foo", "boo");
 
-        let t = TemplateRecipe {
-            template: &tex_path,
-            output: &pdf_path,
-            data: &data,
-            helpers: None,
-        };
+        let recipes: Vec<_> = pdf_paths
+            .iter()
+            .map(|pdf_path| {
+                TemplateRecipe::builder()
+                    .template(&tex_path)
+                    .output(pdf_path)
+                    .data(&data)
+                    .build()
+                    .unwrap()
+            })
+            .collect();
 
-        let _ = render_pdf(&t);
+        let caller = thread::current().id();
+        let mut progress = Vec::new();
+        let results = render_pdf_batch_parallel_with_progress(&recipes, |done, total| {
+            assert_eq!(thread::current().id(), caller);
+            progress.push((done, total));
+        });
+
+        assert_eq!(progress, (1..=8).map(|done| (done, 8)).collect::<Vec<_>>());
+        assert_eq!(results.len(), pdf_paths.len());
+        assert!(results.iter().all(|r| r.is_ok()));
+        assert!(pdf_paths.iter().all(|p| p.exists()));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_render_pdf_async() {
+        let latex = r#"
+            \documentclass{article}
+            \begin{document}
+                Hello, {{foo}}!
+            \end{document}
+        "#;
+
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
 
         {
-            let file = File::open(&pdf_path).expect("Temp TeX cannot be opened");
-            assert_eq!(file.metadata().unwrap().len(), 2767);
+            let mut file = File::create(&tex_path).expect("Temp TeX cannot be created");
+            write!(file, "{}", latex).unwrap();
+        }
+
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        let pdf = render_pdf_async(&tex_path, &data, None).await.unwrap();
+
+        assert!(pdf.starts_with(b"%PDF"));
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_render_pdf_with_timeout() {
+        let latex = r#"
+            \documentclass{article}
+            \begin{document}
+                Hello, {{foo}}!
+            \end{document}
+        "#;
+
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let tex_path = dir.path().join("test.tex");
+        let pdf_path = dir.path().join("test.pdf");
+
+        {
+            let mut file = File::create(&tex_path).expect("Temp TeX cannot be created");
+            write!(file, "{}", latex).unwrap();
         }
+
+        let mut data = HashMap::new();
+        data.insert("foo", "boo");
+
+        let t = TemplateRecipe::builder()
+            .template(&tex_path)
+            .output(&pdf_path)
+            .data(&data)
+            .build()
+            .unwrap();
+
+        assert!(matches!(
+            render_pdf_with_timeout(&t, Duration::ZERO),
+            Err(TexTmplError::Timeout(_))
+        ));
+        assert!(!pdf_path.exists());
+
+        render_pdf_with_timeout(&t, Duration::from_secs(600)).unwrap();
+        assert!(pdf_path.exists());
     }
 }
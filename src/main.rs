@@ -0,0 +1,159 @@
+//! `tex_tmpl` renders a LaTeX & Handlebars template with data from a JSON or YAML file into PDF
+
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use tex_tmpl_rs::{default_helpers, render_pdf, TemplateRecipe};
+
+const USAGE: &str = "Usage: tex_tmpl --template <FILE> --data <FILE> --output <FILE>
+
+Renders the template with the data into a PDF file.
+
+Options:
+  -t, --template <FILE>  LaTeX & Handlebars template
+  -d, --data <FILE>      data as JSON, or as YAML for .yaml and .yml files
+  -o, --output <FILE>    PDF file to write
+  -h, --help             print this help";
+
+/// Paths given on the command line
+#[derive(Debug, PartialEq)]
+struct Args {
+    template: PathBuf,
+    data: PathBuf,
+    output: PathBuf,
+}
+
+/// What the command line asks for
+#[derive(Debug, PartialEq)]
+enum Command {
+    Render(Args),
+    Help,
+}
+
+/// Parses the arguments following the program name
+fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut template = None;
+    let mut data = None;
+    let mut output = None;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let slot = match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-t" | "--template" => &mut template,
+            "-d" | "--data" => &mut data,
+            "-o" | "--output" => &mut output,
+            _ => return Err(format!("unexpected argument {}", arg)),
+        };
+        match args.next() {
+            Some(value) => *slot = Some(PathBuf::from(value)),
+            None => return Err(format!("{} requires a value", arg)),
+        }
+    }
+
+    Ok(Command::Render(Args {
+        template: template.ok_or("--template is required")?,
+        data: data.ok_or("--data is required")?,
+        output: output.ok_or("--output is required")?,
+    }))
+}
+
+/// Reads the data file, as YAML if it has a `.yaml` or `.yml` extension and as JSON otherwise
+fn load_data(path: &Path) -> Result<serde_json::Value, String> {
+    let content = read_to_string(path)
+        .map_err(|e| format!("Cannot read data file {}: {}", path.display(), e))?;
+
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("yaml") | Some("yml") => serde_yaml::from_str(&content)
+            .map_err(|e| format!("Cannot parse YAML in {}: {}", path.display(), e)),
+        _ => serde_json::from_str(&content)
+            .map_err(|e| format!("Cannot parse JSON in {}: {}", path.display(), e)),
+    }
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let data = load_data(&args.data)?;
+
+    let recipe = TemplateRecipe::builder()
+        .template(&args.template)
+        .output(&args.output)
+        .data(&data)
+        .helpers(default_helpers())
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    render_pdf(&recipe).map_err(|e| e.to_string())
+}
+
+fn main() -> ExitCode {
+    match parse_args(std::env::args().skip(1)) {
+        Ok(Command::Help) => {
+            println!("{}", USAGE);
+            ExitCode::SUCCESS
+        }
+        Ok(Command::Render(args)) => match run(args) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("tex_tmpl: {}", e);
+                ExitCode::FAILURE
+            }
+        },
+        Err(e) => {
+            eprintln!("tex_tmpl: {}\n\n{}", e, USAGE);
+            ExitCode::from(2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(
+            parse_args(args(&["-t", "a.tex", "--data", "a.json", "-o", "a.pdf"])),
+            Ok(Command::Render(Args {
+                template: "a.tex".into(),
+                data: "a.json".into(),
+                output: "a.pdf".into(),
+            }))
+        );
+        assert_eq!(parse_args(args(&["--help"])), Ok(Command::Help));
+        assert_eq!(
+            parse_args(args(&["-t", "a.tex", "-d", "a.json"])),
+            Err("--output is required".to_string())
+        );
+        assert_eq!(
+            parse_args(args(&["-t"])),
+            Err("-t requires a value".to_string())
+        );
+    }
+
+    #[test]
+    fn test_load_data() {
+        let dir = tempdir().expect("Temp dir cannot be created");
+
+        let json_path = dir.path().join("data.json");
+        let mut json_file = File::create(&json_path).unwrap();
+        write!(json_file, r#"{{"foo": "boo"}}"#).unwrap();
+
+        let yaml_path = dir.path().join("data.yaml");
+        let mut yaml_file = File::create(&yaml_path).unwrap();
+        write!(yaml_file, "# a comment\nfoo: boo\n").unwrap();
+
+        assert_eq!(load_data(&json_path).unwrap()["foo"], "boo");
+        assert_eq!(load_data(&yaml_path).unwrap()["foo"], "boo");
+        assert!(load_data(&dir.path().join("missing.json"))
+            .unwrap_err()
+            .starts_with("Cannot read data file"));
+    }
+}